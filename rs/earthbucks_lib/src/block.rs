@@ -5,6 +5,7 @@ use crate::header::Header;
 use crate::tx::Tx;
 use crate::var_int::VarInt;
 
+#[derive(Debug, Clone)]
 pub struct Block {
     pub header: Header,
     pub txs: Vec<Tx>,
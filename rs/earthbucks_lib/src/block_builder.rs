@@ -39,6 +39,16 @@ impl BlockBuilder {
         header.merkle_root = root;
         Self::new(header, txs, merkle_txs)
     }
+
+    // builds a block in canonical order: the coinbase first, then the other
+    // txs sorted by id. the merkle root of the header is set to match.
+    pub fn from_canonical_txs(mut header: Header, coinbase_tx: Tx, mut txs: Vec<Tx>) -> Self {
+        txs.sort_by_cached_key(|tx| tx.id());
+        txs.insert(0, coinbase_tx);
+        let merkle_txs = MerkleTxs::new(txs.clone());
+        header.merkle_root = merkle_txs.root;
+        Self::new(header, txs, merkle_txs)
+    }
 }
 
 #[cfg(test)]
@@ -73,4 +83,18 @@ mod tests {
         assert_eq!(bb.header.timestamp, bh.timestamp);
         assert_eq!(bb.header.target, bh.target);
     }
+
+    #[test]
+    fn test_from_canonical_txs() {
+        let header = Header::from_genesis(0);
+        let coinbase_tx = Tx::from_coinbase(Script::from_empty(), Script::from_empty(), 0, 0);
+        let txs: Vec<Tx> = (0..10).map(|i| Tx::new(0, vec![], vec![], i)).collect();
+        let bb = BlockBuilder::from_canonical_txs(header, coinbase_tx.clone(), txs);
+        assert_eq!(bb.txs.len(), 11);
+        assert_eq!(bb.txs[0].id(), coinbase_tx.id());
+        assert!(bb.txs[1..]
+            .windows(2)
+            .all(|pair| pair[0].id() < pair[1].id()));
+        assert_eq!(bb.header.merkle_root, bb.merkle_txs.root);
+    }
 }
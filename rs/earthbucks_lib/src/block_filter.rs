@@ -0,0 +1,303 @@
+use crate::block::Block;
+use crate::buf_reader::BufReader;
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+use crate::hash::{blake3_mac, double_blake3_hash};
+use std::collections::BTreeSet;
+
+// a compact filter of a block, for light wallets to find the blocks that pay
+// to or spend from their pkhs without downloading every block. the items of
+// the filter are the pkhs the outputs pay to, see Script::output_pkhs, and the
+// outpoints the inputs spend. they are stored as a golomb-rice coded set:
+// each item is hashed, keyed by the block id, to a number below
+// n_items * M, and the sorted numbers are stored as coded differences.
+// matching has false positives at a rate of about 1 / M, but no false
+// negatives.
+#[derive(Debug, Clone)]
+pub struct BlockFilter {
+    pub block_id: [u8; 32],
+    pub n_items: u32,
+    pub data: Vec<u8>,
+}
+
+impl BlockFilter {
+    // bits of the remainder of each coded difference
+    pub const P: u8 = 19;
+    pub const M: u64 = 784931;
+
+    pub fn new(block_id: [u8; 32], n_items: u32, data: Vec<u8>) -> Self {
+        Self {
+            block_id,
+            n_items,
+            data,
+        }
+    }
+
+    pub fn pkh_item(pkh: &[u8; 32]) -> Vec<u8> {
+        pkh.to_vec()
+    }
+
+    pub fn outpoint_item(tx_id: &[u8; 32], tx_out_num: u32) -> Vec<u8> {
+        let mut item = tx_id.to_vec();
+        item.extend_from_slice(&tx_out_num.to_be_bytes());
+        item
+    }
+
+    pub fn from_block(block: &Block) -> Self {
+        let mut items = BTreeSet::new();
+        for tx in &block.txs {
+            for output in &tx.outputs {
+                for pkh in output.script.output_pkhs() {
+                    items.insert(Self::pkh_item(&pkh));
+                }
+            }
+            if tx.is_coinbase() {
+                continue;
+            }
+            for input in &tx.inputs {
+                items.insert(Self::outpoint_item(
+                    &input.input_tx_id,
+                    input.input_tx_out_num,
+                ));
+            }
+        }
+        Self::from_items(block.header.id(), items.into_iter().collect())
+    }
+
+    pub fn from_items(block_id: [u8; 32], items: Vec<Vec<u8>>) -> Self {
+        let n_items = items.len() as u32;
+        let mut values = Self::hash_items(&block_id, n_items, &items);
+        values.sort_unstable();
+        let mut bit_writer = BitWriter::default();
+        let mut last_value = 0;
+        for value in values {
+            let delta = value - last_value;
+            last_value = value;
+            // quotient in unary, then the remainder in P bits
+            for _ in 0..(delta >> Self::P) {
+                bit_writer.write_bit(true);
+            }
+            bit_writer.write_bit(false);
+            bit_writer.write_bits(delta, Self::P);
+        }
+        Self::new(block_id, n_items, bit_writer.bytes)
+    }
+
+    fn hash_items(block_id: &[u8; 32], n_items: u32, items: &[Vec<u8>]) -> Vec<u64> {
+        let range = n_items as u128 * Self::M as u128;
+        items
+            .iter()
+            .map(|item| {
+                let hash = blake3_mac(block_id, item);
+                let hash = u64::from_be_bytes(hash[..8].try_into().unwrap());
+                // maps the hash uniformly below range without a division
+                ((hash as u128 * range) >> 64) as u64
+            })
+            .collect()
+    }
+
+    // the sorted hashed items of the filter
+    fn values(&self) -> Option<Vec<u64>> {
+        let mut bit_reader = BitReader::new(&self.data);
+        let mut values = Vec::new();
+        let mut value: u64 = 0;
+        for _ in 0..self.n_items {
+            let mut quotient = 0;
+            while bit_reader.read_bit()? {
+                quotient += 1;
+            }
+            let remainder = bit_reader.read_bits(Self::P)?;
+            value = value.checked_add((quotient << Self::P) | remainder)?;
+            values.push(value);
+        }
+        Some(values)
+    }
+
+    pub fn matches(&self, item: &[u8]) -> bool {
+        self.matches_any(&[item.to_vec()])
+    }
+
+    // true if any of items may be in the filter
+    pub fn matches_any(&self, items: &[Vec<u8>]) -> bool {
+        if self.n_items == 0 || items.is_empty() {
+            return false;
+        }
+        let mut queries = Self::hash_items(&self.block_id, self.n_items, items);
+        queries.sort_unstable();
+        let values = match self.values() {
+            Some(values) => values,
+            None => return false,
+        };
+        // both are sorted, so one pass over each is enough
+        let (mut i, mut j) = (0, 0);
+        while i < values.len() && j < queries.len() {
+            match values[i].cmp(&queries[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => return true,
+            }
+        }
+        false
+    }
+
+    pub fn to_buf(&self) -> Vec<u8> {
+        let mut bw = BufWriter::new();
+        bw.write_var_int(self.n_items as u64);
+        bw.write_var_int(self.data.len() as u64);
+        bw.write(self.data.clone());
+        bw.to_buf()
+    }
+
+    // the block id is not part of the serialized filter. the client knows it
+    // from its header chain.
+    pub fn from_buf(block_id: [u8; 32], buf: Vec<u8>) -> Result<Self, EbxError> {
+        let mut br = BufReader::new(buf);
+        let n_items = br.read_var_int()?;
+        let n_items: u32 = n_items
+            .try_into()
+            .map_err(|_| EbxError::InvalidEncodingError { source: None })?;
+        let data_len = br.read_var_int()? as usize;
+        let data = br.read(data_len)?;
+        if !br.eof() {
+            return Err(EbxError::TooMuchDataError { source: None });
+        }
+        Ok(Self::new(block_id, n_items, data))
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        double_blake3_hash(&self.to_buf())
+    }
+
+    // the filter header commits to this filter and, through prev_header, to
+    // the filters of all earlier blocks. the filter of the genesis block has
+    // a prev_header of zeros.
+    pub fn header(&self, prev_header: &[u8; 32]) -> [u8; 32] {
+        let mut data = self.hash().to_vec();
+        data.extend_from_slice(prev_header);
+        double_blake3_hash(&data)
+    }
+}
+
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    n_bits: usize,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.n_bits.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+        if bit {
+            *self.bytes.last_mut().unwrap() |= 0x80 >> (self.n_bits % 8);
+        }
+        self.n_bits += 1;
+    }
+
+    // the lowest n_bits of value, most significant first
+    fn write_bits(&mut self, value: u64, n_bits: u8) {
+        for i in (0..n_bits).rev() {
+            self.write_bit((value >> i) & 1 == 1);
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    n_bits: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, n_bits: 0 }
+    }
+
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte = self.bytes.get(self.n_bits / 8)?;
+        let bit = byte & (0x80 >> (self.n_bits % 8)) != 0;
+        self.n_bits += 1;
+        Some(bit)
+    }
+
+    fn read_bits(&mut self, n_bits: u8) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..n_bits {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header_chain::HeaderChain;
+    use crate::pkh::Pkh;
+    use crate::script::Script;
+    use crate::tx::Tx;
+    use crate::tx_in::TxIn;
+    use crate::tx_out::TxOut;
+
+    fn block() -> Block {
+        let lch = HeaderChain::new();
+        let pkh = Pkh::from_pub_key_buffer(vec![2; 33]);
+        let coinbase_tx = lch.get_next_coinbase_tx(&pkh, &"example.com".to_string());
+        let input = TxIn::new([3; 32], 1, Script::from_empty(), 0);
+        let outputs = vec![
+            TxOut::new(10, Script::from_pkhx_1h_output(&[4; 32])),
+            TxOut::new(10, Script::from_pkhxr_90d_60d_output(&[5; 32], &[6; 32])),
+        ];
+        let tx = Tx::new(1, vec![input], outputs, 0);
+        let header = lch.get_next_header([0; 32], 0).unwrap();
+        Block::new(header, vec![coinbase_tx, tx])
+    }
+
+    #[test]
+    fn test_matches() {
+        let block = block();
+        let filter = BlockFilter::from_block(&block);
+        let pkh = Pkh::from_pub_key_buffer(vec![2; 33]);
+        assert_eq!(filter.n_items, 5);
+        for pkh in [pkh.buf, [4; 32], [5; 32], [6; 32]] {
+            assert!(filter.matches(&BlockFilter::pkh_item(&pkh)));
+        }
+        assert!(filter.matches(&BlockFilter::outpoint_item(&[3; 32], 1)));
+
+        assert!(!filter.matches(&BlockFilter::pkh_item(&[7; 32])));
+        assert!(!filter.matches(&BlockFilter::outpoint_item(&[3; 32], 0)));
+        assert!(filter.matches_any(&[
+            BlockFilter::pkh_item(&[7; 32]),
+            BlockFilter::pkh_item(&[4; 32])
+        ]));
+        assert!(!filter.matches_any(&[]));
+    }
+
+    #[test]
+    fn test_many_items() {
+        let items: Vec<Vec<u8>> = (0..1000u32)
+            .map(|i| BlockFilter::outpoint_item(&[1; 32], i))
+            .collect();
+        let filter = BlockFilter::from_items([9; 32], items.clone());
+        // about 21 bits per item
+        assert!(filter.data.len() < 1000 * 22 / 8);
+        assert!(items.iter().all(|item| filter.matches(item)));
+        let false_positives = (1000..11000u32)
+            .filter(|i| filter.matches(&BlockFilter::outpoint_item(&[1; 32], *i)))
+            .count();
+        assert!(false_positives < 5);
+
+        let empty = BlockFilter::from_items([9; 32], vec![]);
+        assert!(!empty.matches(&items[0]));
+    }
+
+    #[test]
+    fn test_to_buf_and_from_buf() {
+        let block = block();
+        let filter = BlockFilter::from_block(&block);
+        let decoded = BlockFilter::from_buf(block.header.id(), filter.to_buf()).unwrap();
+        assert_eq!(decoded.to_buf(), filter.to_buf());
+        assert!(decoded.matches(&BlockFilter::pkh_item(&[4; 32])));
+        assert_ne!(filter.header(&[0; 32]), filter.header(&[1; 32]));
+    }
+}
@@ -0,0 +1,69 @@
+use crate::block_filter::BlockFilter;
+
+// the filter headers of a chain of blocks, one per block. each header commits
+// to the filter of its block and to the header before it, so a light wallet
+// that has the filter headers from one peer can check a filter from any other
+// peer before trusting it.
+#[derive(Default, Clone)]
+pub struct BlockFilterChain {
+    pub headers: Vec<[u8; 32]>,
+}
+
+impl BlockFilterChain {
+    pub fn new() -> Self {
+        Self {
+            headers: Vec::new(),
+        }
+    }
+
+    pub fn get_tip(&self) -> Option<&[u8; 32]> {
+        self.headers.last()
+    }
+
+    // adds the filter of the next block and returns its header
+    pub fn add(&mut self, filter: &BlockFilter) -> [u8; 32] {
+        let prev_header = self.headers.last().copied().unwrap_or([0; 32]);
+        let header = filter.header(&prev_header);
+        self.headers.push(header);
+        header
+    }
+
+    // true if filter is the filter committed to for block_num
+    pub fn filter_is_valid(&self, block_num: u32, filter: &BlockFilter) -> bool {
+        let block_num = block_num as usize;
+        let header = match self.headers.get(block_num) {
+            Some(header) => header,
+            None => return false,
+        };
+        let prev_header = match block_num {
+            0 => [0; 32],
+            _ => self.headers[block_num - 1],
+        };
+        filter.header(&prev_header) == *header
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_is_valid() {
+        let filters: Vec<BlockFilter> = (0..3u8)
+            .map(|i| BlockFilter::from_items([i; 32], vec![vec![i; 32]]))
+            .collect();
+        let mut chain = BlockFilterChain::new();
+        for filter in &filters {
+            chain.add(filter);
+        }
+        assert_eq!(chain.headers.len(), 3);
+        for (block_num, filter) in filters.iter().enumerate() {
+            assert!(chain.filter_is_valid(block_num as u32, filter));
+        }
+        assert!(!chain.filter_is_valid(1, &filters[2]));
+        assert!(!chain.filter_is_valid(3, &filters[2]));
+
+        let forged = BlockFilter::from_items([1; 32], vec![vec![1; 32], vec![2; 32]]);
+        assert!(!chain.filter_is_valid(1, &forged));
+    }
+}
@@ -0,0 +1,711 @@
+use crate::block::Block;
+use crate::buf_reader::BufReader;
+use crate::error::EbxError;
+use crate::hash::blake3_hash;
+use crate::header::Header;
+use crate::header_chain::HeaderChain;
+use crate::tx::Tx;
+use crate::var_int::VarInt;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+// where a block is stored: the segment file, and the offset and length of its
+// record in that file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockLocation {
+    pub block_num: u32,
+    pub segment_num: u32,
+    pub offset: u64,
+    pub len: u32,
+}
+
+// an append-only, file-backed store of blocks. blocks are appended as records
+// to segment files, blk00000.dat, blk00001.dat and so on, and a new segment
+// is started when the current one would grow past max_segment_size. a
+// record is:
+//
+//     magic, block length (u32), tx count (u32), tx offsets (u32 each),
+//     block, checksum (blake3 of everything before it)
+//
+// the tx offsets allow reading one tx without reading its block. the index
+// file, index.dat, has one fixed size entry per record and is appended to
+// after the record is synced. on open, records that were written but not
+// indexed, e.g. after an unclean shutdown, are indexed, and a torn record at
+// the end of the last segment is cut off.
+//
+// with pruning, whole segments of old blocks are deleted, oldest first, once
+// every block in them is older than the safety period before the validated
+// tip. their headers are kept in pruned.dat, written before the segment is
+// deleted.
+pub struct BlockStore {
+    dir: PathBuf,
+    max_segment_size: u64,
+    locations: HashMap<[u8; 32], BlockLocation>,
+    block_ids: BTreeMap<u32, Vec<[u8; 32]>>,
+    segment_max_block_nums: BTreeMap<u32, u32>,
+    index_file: File,
+    segment_num: u32,
+    segment_len: u64,
+    n_pruned_segments: u32, // segments below this are pruned
+    pruned_headers: HashMap<[u8; 32], Header>,
+    pruned_file: File,
+}
+
+impl BlockStore {
+    pub const DEFAULT_MAX_SEGMENT_SIZE: u64 = 128 * 1024 * 1024;
+    pub const MAGIC: [u8; 4] = *b"EBXB";
+    const RECORD_HEADER_SIZE: u64 = 4 + 4 + 4;
+    const CHECKSUM_SIZE: u64 = 32;
+    const INDEX_ENTRY_SIZE: u64 = 32 + 4 + 4 + 8 + 4;
+
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, EbxError> {
+        Self::open_with_max_segment_size(dir, Self::DEFAULT_MAX_SEGMENT_SIZE)
+    }
+
+    pub fn open_with_max_segment_size(
+        dir: impl AsRef<Path>,
+        max_segment_size: u64,
+    ) -> Result<Self, EbxError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(io_error)?;
+        let open_append = |name: &str| {
+            OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(dir.join(name))
+                .map_err(io_error)
+        };
+        let index_file = open_append("index.dat")?;
+        let pruned_file = open_append("pruned.dat")?;
+        let mut block_store = Self {
+            dir,
+            max_segment_size,
+            locations: HashMap::new(),
+            block_ids: BTreeMap::new(),
+            segment_max_block_nums: BTreeMap::new(),
+            index_file,
+            segment_num: 0,
+            segment_len: 0,
+            n_pruned_segments: 0,
+            pruned_headers: HashMap::new(),
+            pruned_file,
+        };
+        block_store.load_pruned()?;
+        block_store.load_index()?;
+        block_store.recover()?;
+        Ok(block_store)
+    }
+
+    fn segment_path(&self, segment_num: u32) -> PathBuf {
+        self.dir.join(format!("blk{:05}.dat", segment_num))
+    }
+
+    fn segment_len(&self, segment_num: u32) -> Option<u64> {
+        fs::metadata(self.segment_path(segment_num))
+            .ok()
+            .map(|metadata| metadata.len())
+    }
+
+    // reads the headers of pruned segments. a pruned record is:
+    //
+    //     segment number (u32), header count (u32), headers, checksum
+    //
+    // a torn record at the end is cut off, and segment files left over from
+    // a prune that did not finish are deleted.
+    fn load_pruned(&mut self) -> Result<(), EbxError> {
+        let mut buf = Vec::new();
+        self.pruned_file
+            .seek(SeekFrom::Start(0))
+            .map_err(io_error)?;
+        self.pruned_file.read_to_end(&mut buf).map_err(io_error)?;
+        let mut offset = 0;
+        while buf.len() - offset >= 8 {
+            let segment_num = u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap());
+            let n_headers =
+                u32::from_be_bytes(buf[offset + 4..offset + 8].try_into().unwrap()) as usize;
+            let len = 8 + n_headers * Header::SIZE + Self::CHECKSUM_SIZE as usize;
+            if buf.len() - offset < len {
+                break;
+            }
+            let (body, checksum) = buf[offset..offset + len].split_at(len - 32);
+            if blake3_hash(body) != checksum {
+                break;
+            }
+            for header_buf in body[8..].chunks_exact(Header::SIZE) {
+                let header = Header::from_buf(header_buf.try_into().unwrap())?;
+                self.pruned_headers.insert(header.id(), header);
+            }
+            self.n_pruned_segments = segment_num + 1;
+            offset += len;
+        }
+        if offset != buf.len() {
+            self.pruned_file.set_len(offset as u64).map_err(io_error)?;
+        }
+        for segment_num in 0..self.n_pruned_segments {
+            Self::remove_segment(&self.segment_path(segment_num))?;
+        }
+        Ok(())
+    }
+
+    fn remove_segment(path: &Path) -> Result<(), EbxError> {
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(io_error(error)),
+            _ => Ok(()),
+        }
+    }
+
+    // reads the index entries that point to complete records. entries past
+    // the first bad one are dropped.
+    fn load_index(&mut self) -> Result<(), EbxError> {
+        let mut buf = Vec::new();
+        self.index_file.seek(SeekFrom::Start(0)).map_err(io_error)?;
+        self.index_file.read_to_end(&mut buf).map_err(io_error)?;
+        let mut n_valid = 0;
+        for entry in buf.chunks_exact(Self::INDEX_ENTRY_SIZE as usize) {
+            let block_id: [u8; 32] = entry[..32].try_into().unwrap();
+            let mut br = BufReader::new(entry[32..].to_vec());
+            let location = BlockLocation {
+                block_num: br.read_u32_be()?,
+                segment_num: br.read_u32_be()?,
+                offset: br.read_u64_be()?,
+                len: br.read_u32_be()?,
+            };
+            if location.segment_num >= self.n_pruned_segments {
+                match self.segment_len(location.segment_num) {
+                    Some(len) if location.offset + location.len as u64 <= len => {}
+                    _ => break,
+                }
+            }
+            self.insert_location(block_id, location);
+            n_valid += 1;
+        }
+        if n_valid * Self::INDEX_ENTRY_SIZE != buf.len() as u64 {
+            self.index_file
+                .set_len(n_valid * Self::INDEX_ENTRY_SIZE)
+                .map_err(io_error)?;
+        }
+        Ok(())
+    }
+
+    // indexes the records after the last indexed one, and cuts off a torn
+    // record at the end
+    fn recover(&mut self) -> Result<(), EbxError> {
+        let last = self
+            .locations
+            .values()
+            .max_by_key(|location| (location.segment_num, location.offset));
+        let (mut segment_num, mut offset) = match last {
+            Some(location) => (location.segment_num, location.offset + location.len as u64),
+            None => (0, 0),
+        };
+        while let Some(segment_len) = self.segment_len(segment_num) {
+            let mut file = File::open(self.segment_path(segment_num)).map_err(io_error)?;
+            while offset < segment_len {
+                match Self::read_record(&mut file, offset)? {
+                    Some((block, len)) => {
+                        let location = BlockLocation {
+                            block_num: block.header.block_num,
+                            segment_num,
+                            offset,
+                            len,
+                        };
+                        self.write_index_entry(&block.header.id(), &location)?;
+                        offset += len as u64;
+                    }
+                    None => {
+                        // a torn write. appends only move to a new segment
+                        // after a complete record, so this is the end.
+                        OpenOptions::new()
+                            .write(true)
+                            .open(self.segment_path(segment_num))
+                            .and_then(|file| file.set_len(offset))
+                            .map_err(io_error)?;
+                        break;
+                    }
+                }
+            }
+            self.segment_num = segment_num;
+            self.segment_len = offset;
+            if self.segment_len(segment_num + 1).is_none() {
+                break;
+            }
+            segment_num += 1;
+            offset = 0;
+        }
+        Ok(())
+    }
+
+    // the block and the length of the record at offset, or None if the
+    // record is incomplete or corrupt
+    fn read_record(file: &mut File, offset: u64) -> Result<Option<(Block, u32)>, EbxError> {
+        let mut record_header = [0u8; Self::RECORD_HEADER_SIZE as usize];
+        file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        if file.read_exact(&mut record_header).is_err() || record_header[..4] != Self::MAGIC {
+            return Ok(None);
+        }
+        let block_len = u32::from_be_bytes(record_header[4..8].try_into().unwrap()) as u64;
+        let n_txs = u32::from_be_bytes(record_header[8..12].try_into().unwrap()) as u64;
+        let len = Self::RECORD_HEADER_SIZE + n_txs * 4 + block_len + Self::CHECKSUM_SIZE;
+        let file_len = file.metadata().map_err(io_error)?.len();
+        if offset + len > file_len || len > u32::MAX as u64 {
+            return Ok(None);
+        }
+        let mut record = vec![0u8; len as usize];
+        file.seek(SeekFrom::Start(offset)).map_err(io_error)?;
+        file.read_exact(&mut record).map_err(io_error)?;
+        let (body, checksum) = record.split_at(record.len() - Self::CHECKSUM_SIZE as usize);
+        if blake3_hash(body) != checksum {
+            return Ok(None);
+        }
+        let block_start = (Self::RECORD_HEADER_SIZE + n_txs * 4) as usize;
+        match Block::from_buf(body[block_start..].to_vec()) {
+            Ok(block) => Ok(Some((block, len as u32))),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn insert_location(&mut self, block_id: [u8; 32], location: BlockLocation) {
+        self.locations.insert(block_id, location);
+        self.block_ids
+            .entry(location.block_num)
+            .or_default()
+            .push(block_id);
+        let max_block_num = self
+            .segment_max_block_nums
+            .entry(location.segment_num)
+            .or_insert(location.block_num);
+        *max_block_num = location.block_num.max(*max_block_num);
+    }
+
+    fn write_index_entry(
+        &mut self,
+        block_id: &[u8; 32],
+        location: &BlockLocation,
+    ) -> Result<(), EbxError> {
+        let mut entry = block_id.to_vec();
+        entry.extend_from_slice(&location.block_num.to_be_bytes());
+        entry.extend_from_slice(&location.segment_num.to_be_bytes());
+        entry.extend_from_slice(&location.offset.to_be_bytes());
+        entry.extend_from_slice(&location.len.to_be_bytes());
+        self.index_file.write_all(&entry).map_err(io_error)?;
+        self.index_file.sync_data().map_err(io_error)?;
+        self.insert_location(*block_id, *location);
+        Ok(())
+    }
+
+    fn to_record(block: &Block) -> Vec<u8> {
+        let mut block_buf = block.header.to_buf().to_vec();
+        block_buf.extend(VarInt::from_u64(block.txs.len() as u64).to_buf());
+        let mut tx_offsets = Vec::with_capacity(block.txs.len());
+        for tx in &block.txs {
+            tx_offsets.push(block_buf.len() as u32);
+            block_buf.extend(tx.to_buf());
+        }
+        let mut record = Self::MAGIC.to_vec();
+        record.extend_from_slice(&(block_buf.len() as u32).to_be_bytes());
+        record.extend_from_slice(&(tx_offsets.len() as u32).to_be_bytes());
+        for tx_offset in tx_offsets {
+            record.extend_from_slice(&tx_offset.to_be_bytes());
+        }
+        record.extend(block_buf);
+        let checksum = blake3_hash(&record);
+        record.extend_from_slice(&checksum);
+        record
+    }
+
+    // appends block and returns its id. a block that is already stored is
+    // not written again.
+    pub fn append(&mut self, block: &Block) -> Result<[u8; 32], EbxError> {
+        let block_id = block.header.id();
+        if self.contains(&block_id) {
+            return Ok(block_id);
+        }
+        let record = Self::to_record(block);
+        if record.len() as u64 > u32::MAX as u64 {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "block is too large to store".to_string(),
+            });
+        }
+        if self.segment_len > 0 && self.segment_len + record.len() as u64 > self.max_segment_size {
+            self.segment_num += 1;
+            self.segment_len = 0;
+        }
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.segment_path(self.segment_num))
+            .map_err(io_error)?;
+        file.write_all(&record).map_err(io_error)?;
+        file.sync_data().map_err(io_error)?;
+        let location = BlockLocation {
+            block_num: block.header.block_num,
+            segment_num: self.segment_num,
+            offset: self.segment_len,
+            len: record.len() as u32,
+        };
+        self.segment_len += record.len() as u64;
+        self.write_index_entry(&block_id, &location)?;
+        Ok(block_id)
+    }
+
+    // deletes the bodies of blocks below block_num, keeping their headers. a
+    // segment is only deleted if all of its blocks are below block_num and all
+    // earlier segments are deleted, and the segment being appended to is never
+    // deleted, so some older blocks may be kept. returns the number of
+    // segments deleted.
+    pub fn prune(&mut self, block_num: u32) -> Result<u32, EbxError> {
+        let mut n_pruned = 0;
+        while self.n_pruned_segments < self.segment_num {
+            let segment_num = self.n_pruned_segments;
+            if let Some(max_block_num) = self.segment_max_block_nums.get(&segment_num) {
+                if *max_block_num >= block_num {
+                    break;
+                }
+            }
+            let mut block_ids: Vec<([u8; 32], u64)> = self
+                .locations
+                .iter()
+                .filter(|(_, location)| location.segment_num == segment_num)
+                .map(|(block_id, location)| (*block_id, location.offset))
+                .collect();
+            block_ids.sort_by_key(|(_, offset)| *offset);
+            let mut headers = Vec::with_capacity(block_ids.len());
+            for (block_id, _) in &block_ids {
+                headers.push(self.get_header(block_id)?.unwrap());
+            }
+
+            let mut record = segment_num.to_be_bytes().to_vec();
+            record.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+            for header in &headers {
+                record.extend_from_slice(&header.to_buf());
+            }
+            let checksum = blake3_hash(&record);
+            record.extend_from_slice(&checksum);
+            self.pruned_file.write_all(&record).map_err(io_error)?;
+            self.pruned_file.sync_data().map_err(io_error)?;
+            Self::remove_segment(&self.segment_path(segment_num))?;
+
+            for header in headers {
+                self.pruned_headers.insert(header.id(), header);
+            }
+            self.n_pruned_segments += 1;
+            n_pruned += 1;
+        }
+        Ok(n_pruned)
+    }
+
+    // prunes the blocks older than HeaderChain::LENGTH_SAFETY_PERIOD before
+    // the tip of a validated chain of chain_len headers, e.g. lch.len(). the
+    // stored blocks themselves are not used to find the tip, as a block from
+    // a fork or one not yet validated may be far ahead of it.
+    pub fn prune_for_tip(&mut self, chain_len: u32) -> Result<u32, EbxError> {
+        self.prune(chain_len.saturating_sub(HeaderChain::LENGTH_SAFETY_PERIOD))
+    }
+
+    pub fn is_pruned(&self, block_id: &[u8; 32]) -> bool {
+        self.pruned_headers.contains_key(block_id)
+    }
+
+    // the location of a block that is stored and not pruned
+    fn unpruned_location(&self, block_id: &[u8; 32]) -> Result<Option<&BlockLocation>, EbxError> {
+        if self.is_pruned(block_id) {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "block is pruned".to_string(),
+            });
+        }
+        Ok(self.locations.get(block_id))
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+
+    pub fn contains(&self, block_id: &[u8; 32]) -> bool {
+        self.locations.contains_key(block_id)
+    }
+
+    pub fn location(&self, block_id: &[u8; 32]) -> Option<BlockLocation> {
+        self.locations.get(block_id).copied()
+    }
+
+    // the ids of the stored blocks at block_num, in the order they were
+    // stored. there is more than one if blocks from competing chains were
+    // stored.
+    pub fn block_ids_at(&self, block_num: u32) -> Vec<[u8; 32]> {
+        self.block_ids.get(&block_num).cloned().unwrap_or_default()
+    }
+
+    fn read_at(
+        &self,
+        location: &BlockLocation,
+        offset: u64,
+        len: u64,
+    ) -> Result<Vec<u8>, EbxError> {
+        let mut file = File::open(self.segment_path(location.segment_num)).map_err(io_error)?;
+        file.seek(SeekFrom::Start(location.offset + offset))
+            .map_err(io_error)?;
+        let mut buf = vec![0u8; len as usize];
+        file.read_exact(&mut buf).map_err(io_error)?;
+        Ok(buf)
+    }
+
+    // an error if the block is pruned
+    pub fn get(&self, block_id: &[u8; 32]) -> Result<Option<Block>, EbxError> {
+        let location = match self.unpruned_location(block_id)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let record_header = self.read_at(location, 0, Self::RECORD_HEADER_SIZE)?;
+        let block_len = u32::from_be_bytes(record_header[4..8].try_into().unwrap()) as u64;
+        let n_txs = u32::from_be_bytes(record_header[8..12].try_into().unwrap()) as u64;
+        let block_start = Self::RECORD_HEADER_SIZE + n_txs * 4;
+        let block_buf = self.read_at(location, block_start, block_len)?;
+        Ok(Some(Block::from_buf(block_buf)?))
+    }
+
+    // the headers of pruned blocks are kept
+    pub fn get_header(&self, block_id: &[u8; 32]) -> Result<Option<Header>, EbxError> {
+        if let Some(header) = self.pruned_headers.get(block_id) {
+            return Ok(Some(header.clone()));
+        }
+        let location = match self.locations.get(block_id) {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let record_header = self.read_at(location, 0, Self::RECORD_HEADER_SIZE)?;
+        let n_txs = u32::from_be_bytes(record_header[8..12].try_into().unwrap()) as u64;
+        let block_start = Self::RECORD_HEADER_SIZE + n_txs * 4;
+        let header_buf = self.read_at(location, block_start, Header::SIZE as u64)?;
+        Ok(Some(Header::from_buf(header_buf.try_into().unwrap())?))
+    }
+
+    // reads only the tx at tx_num of the block. an error if the block is
+    // pruned.
+    pub fn get_tx(&self, block_id: &[u8; 32], tx_num: u32) -> Result<Option<Tx>, EbxError> {
+        let location = match self.unpruned_location(block_id)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let record_header = self.read_at(location, 0, Self::RECORD_HEADER_SIZE)?;
+        let block_len = u32::from_be_bytes(record_header[4..8].try_into().unwrap());
+        let n_txs = u32::from_be_bytes(record_header[8..12].try_into().unwrap());
+        if tx_num >= n_txs {
+            return Ok(None);
+        }
+        // the offset of this tx and of the next one, or the end of the block
+        let n_offsets = if tx_num + 1 < n_txs { 2 } else { 1 };
+        let offsets = self.read_at(
+            location,
+            Self::RECORD_HEADER_SIZE + tx_num as u64 * 4,
+            n_offsets * 4,
+        )?;
+        let tx_start = u32::from_be_bytes(offsets[..4].try_into().unwrap());
+        let tx_end = match n_offsets {
+            2 => u32::from_be_bytes(offsets[4..].try_into().unwrap()),
+            _ => block_len,
+        };
+        let block_start = Self::RECORD_HEADER_SIZE + n_txs as u64 * 4;
+        let tx_buf = self.read_at(
+            location,
+            block_start + tx_start as u64,
+            (tx_end - tx_start) as u64,
+        )?;
+        Ok(Some(Tx::from_buf(tx_buf)?))
+    }
+}
+
+fn io_error(error: std::io::Error) -> EbxError {
+    EbxError::GenericError {
+        source: None,
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Script;
+    use crate::tx_in::TxIn;
+    use crate::tx_out::TxOut;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("earthbucks_block_store_{}", rand::random::<u64>()))
+    }
+
+    fn block(block_num: u32, n_txs: u32) -> Block {
+        let mut header = Header::from_genesis(0);
+        header.block_num = block_num;
+        let txs = (0..n_txs)
+            .map(|i| {
+                let input = TxIn::new([i as u8; 32], i, Script::from_empty(), 0);
+                let output = TxOut::new(i as u64, Script::from_pkh_output(&[1; 32]));
+                Tx::new(1, vec![input], vec![output], block_num)
+            })
+            .collect();
+        Block::new(header, txs)
+    }
+
+    #[test]
+    fn test_append_and_get() {
+        let dir = temp_dir();
+        let blocks: Vec<Block> = (0..5).map(|block_num| block(block_num, 3)).collect();
+        {
+            // small segments, so the blocks are spread over several files
+            let mut block_store = BlockStore::open_with_max_segment_size(&dir, 1000).unwrap();
+            for block in &blocks {
+                block_store.append(block).unwrap();
+            }
+            block_store.append(&blocks[0]).unwrap();
+            assert_eq!(block_store.len(), 5);
+            assert!(
+                block_store
+                    .location(&blocks[4].header.id())
+                    .unwrap()
+                    .segment_num
+                    > 0
+            );
+        }
+
+        let block_store = BlockStore::open_with_max_segment_size(&dir, 1000).unwrap();
+        assert_eq!(block_store.len(), 5);
+        for block in &blocks {
+            let block_id = block.header.id();
+            let stored = block_store.get(&block_id).unwrap().unwrap();
+            assert_eq!(stored.to_buf(), block.to_buf());
+            assert_eq!(
+                block_store.get_header(&block_id).unwrap().unwrap().id(),
+                block_id
+            );
+            assert_eq!(
+                block_store.block_ids_at(block.header.block_num),
+                vec![block_id]
+            );
+            for (tx_num, tx) in block.txs.iter().enumerate() {
+                let stored_tx = block_store.get_tx(&block_id, tx_num as u32).unwrap();
+                assert_eq!(stored_tx.unwrap().id(), tx.id());
+            }
+            assert!(block_store.get_tx(&block_id, 3).unwrap().is_none());
+        }
+        assert!(block_store.get(&[0; 32]).unwrap().is_none());
+        assert!(block_store.block_ids_at(5).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = temp_dir();
+        let blocks: Vec<Block> = (0..6).map(|block_num| block(block_num, 3)).collect();
+        let mut block_store = BlockStore::open_with_max_segment_size(&dir, 1000).unwrap();
+        for block in &blocks {
+            block_store.append(block).unwrap();
+        }
+        let n_segments = block_store
+            .location(&blocks[5].header.id())
+            .unwrap()
+            .segment_num
+            + 1;
+        assert!(n_segments > 2);
+        assert_eq!(block_store.prune(0).unwrap(), 0);
+        assert!(block_store.prune(3).unwrap() > 0);
+
+        let check = |block_store: &BlockStore| {
+            for block in &blocks {
+                let block_id = block.header.id();
+                let header = block_store.get_header(&block_id).unwrap().unwrap();
+                assert_eq!(header.id(), block_id);
+                if block_store.is_pruned(&block_id) {
+                    assert!(block.header.block_num < 3);
+                    assert!(block_store.get(&block_id).is_err());
+                    assert!(block_store.get_tx(&block_id, 0).is_err());
+                } else {
+                    let stored = block_store.get(&block_id).unwrap().unwrap();
+                    assert_eq!(stored.to_buf(), block.to_buf());
+                }
+            }
+            assert!(block_store.is_pruned(&blocks[0].header.id()));
+            assert!(!block_store.is_pruned(&blocks[5].header.id()));
+            assert_eq!(block_store.len(), 6);
+        };
+        check(&block_store);
+        assert!(!dir.join("blk00000.dat").exists());
+        drop(block_store);
+        let mut block_store = BlockStore::open_with_max_segment_size(&dir, 1000).unwrap();
+        check(&block_store);
+
+        // a stored block far ahead of the chain does not prune anything, only
+        // the tip of the validated chain does
+        let far_block = block(6 + HeaderChain::LENGTH_SAFETY_PERIOD, 3);
+        block_store.append(&far_block).unwrap();
+        assert!(!block_store.is_pruned(&blocks[4].header.id()));
+        assert_eq!(block_store.prune_for_tip(6).unwrap(), 0);
+        block_store
+            .prune_for_tip(7 + HeaderChain::LENGTH_SAFETY_PERIOD)
+            .unwrap();
+        let segment_num = block_store
+            .location(&far_block.header.id())
+            .unwrap()
+            .segment_num;
+        for block in &blocks {
+            let location = block_store.location(&block.header.id()).unwrap();
+            assert_eq!(
+                block_store.is_pruned(&block.header.id()),
+                location.segment_num < segment_num
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recover_after_unclean_shutdown() {
+        let dir = temp_dir();
+        let blocks: Vec<Block> = (0..3).map(|block_num| block(block_num, 2)).collect();
+        {
+            let mut block_store = BlockStore::open(&dir).unwrap();
+            for block in &blocks {
+                block_store.append(block).unwrap();
+            }
+        }
+        // the last block was written but not indexed, and the index has half
+        // an entry
+        let index_len = fs::metadata(dir.join("index.dat")).unwrap().len();
+        let index_file = OpenOptions::new()
+            .write(true)
+            .open(dir.join("index.dat"))
+            .unwrap();
+        index_file
+            .set_len(index_len - BlockStore::INDEX_ENTRY_SIZE / 2)
+            .unwrap();
+        // and half of a fourth block was written
+        let record = BlockStore::to_record(&block(3, 2));
+        let mut segment = OpenOptions::new()
+            .append(true)
+            .open(dir.join("blk00000.dat"))
+            .unwrap();
+        segment.write_all(&record[..record.len() / 2]).unwrap();
+
+        let mut block_store = BlockStore::open(&dir).unwrap();
+        assert_eq!(block_store.len(), 3);
+        for block in &blocks {
+            let stored = block_store.get(&block.header.id()).unwrap().unwrap();
+            assert_eq!(stored.to_buf(), block.to_buf());
+        }
+        // the torn record was cut off, so the next block follows the last
+        // complete one
+        let block = block(3, 2);
+        block_store.append(&block).unwrap();
+        let block_store = BlockStore::open(&dir).unwrap();
+        assert_eq!(block_store.len(), 4);
+        let stored = block_store.get(&block.header.id()).unwrap().unwrap();
+        assert_eq!(stored.to_buf(), block.to_buf());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+use crate::block::Block;
+use crate::block_builder::BlockBuilder;
+use crate::error::EbxError;
+use crate::header::Header;
+use crate::header_chain::HeaderChain;
+use crate::mempool::Mempool;
+use crate::merkle_txs::MerkleTxs;
+use crate::pkh::Pkh;
+use crate::tx::Tx;
+use std::collections::HashMap;
+
+// a block template is the next block for the longest chain, complete except
+// for proof of work: the coinbase paying the mine, the mempool txs with
+// parents before children, and a header with the merkle root filled in.
+pub struct BlockTemplate {
+    pub header: Header,
+    pub txs: Vec<Tx>,
+    pub merkle_txs: MerkleTxs,
+}
+
+impl BlockTemplate {
+    pub fn new(header: Header, txs: Vec<Tx>, merkle_txs: MerkleTxs) -> Self {
+        Self {
+            header,
+            txs,
+            merkle_txs,
+        }
+    }
+
+    fn check_mempool(lch: &HeaderChain, mempool: &Mempool) -> Result<(), EbxError> {
+        let building_block_num = lch.len();
+        if mempool.working_block_num() != building_block_num {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "mempool is not at the next block".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn from_mempool(
+        lch: &HeaderChain,
+        mempool: &Mempool,
+        domain: &String,
+        pkh: &Pkh,
+        new_timestamp: u64,
+    ) -> Result<Self, EbxError> {
+        Self::check_mempool(lch, mempool)?;
+        let coinbase_tx = lch.get_next_coinbase_tx(pkh, domain);
+        let mempool_txs: Vec<Tx> = mempool.txs().into_iter().cloned().collect();
+        let mut txs = vec![coinbase_tx];
+        txs.extend(Self::sort_parents_first(mempool_txs));
+
+        let merkle_txs = MerkleTxs::new(txs.clone());
+        let header = lch.get_next_header(merkle_txs.root, new_timestamp)?;
+        Ok(Self::new(header, txs, merkle_txs))
+    }
+
+    // like from_mempool, but with the txs after the coinbase sorted by id,
+    // for chains that use the canonical ordering rule
+    pub fn from_mempool_canonical(
+        lch: &HeaderChain,
+        mempool: &Mempool,
+        domain: &String,
+        pkh: &Pkh,
+        new_timestamp: u64,
+    ) -> Result<Self, EbxError> {
+        Self::check_mempool(lch, mempool)?;
+        let coinbase_tx = lch.get_next_coinbase_tx(pkh, domain);
+        let mempool_txs: Vec<Tx> = mempool.txs().into_iter().cloned().collect();
+        let header = lch.get_next_header([0; 32], new_timestamp)?;
+        let block_builder = BlockBuilder::from_canonical_txs(header, coinbase_tx, mempool_txs);
+        Ok(Self::new(
+            block_builder.header,
+            block_builder.txs,
+            block_builder.merkle_txs,
+        ))
+    }
+
+    // orders txs so that every tx comes after the txs it spends from, keeping
+    // the original order wherever possible
+    pub fn sort_parents_first(txs: Vec<Tx>) -> Vec<Tx> {
+        let tx_nums: HashMap<[u8; 32], usize> = txs
+            .iter()
+            .enumerate()
+            .map(|(tx_num, tx)| (tx.id(), tx_num))
+            .collect();
+        let mut visited = vec![false; txs.len()];
+        let mut sorted_tx_nums = Vec::with_capacity(txs.len());
+        for tx_num in 0..txs.len() {
+            // depth first, without recursion, so long chains can't overflow
+            // the stack. a tx is emitted when it is popped the second time,
+            // after all of its parents.
+            let mut stack = vec![(tx_num, false)];
+            while let Some((tx_num, parents_done)) = stack.pop() {
+                if parents_done {
+                    sorted_tx_nums.push(tx_num);
+                    continue;
+                }
+                if visited[tx_num] {
+                    continue;
+                }
+                visited[tx_num] = true;
+                stack.push((tx_num, true));
+                for input in txs[tx_num].inputs.iter().rev() {
+                    if let Some(&parent_num) = tx_nums.get(&input.input_tx_id) {
+                        if !visited[parent_num] {
+                            stack.push((parent_num, false));
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut txs: Vec<Option<Tx>> = txs.into_iter().map(Some).collect();
+        sorted_tx_nums
+            .into_iter()
+            .map(|tx_num| txs[tx_num].take().unwrap())
+            .collect()
+    }
+
+    // the block to search a nonce for. the header's nonce and work fields are
+    // left for the miner.
+    pub fn to_block(&self) -> Block {
+        Block::new(self.header.clone(), self.txs.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_verifier::BlockVerifier;
+    use crate::key_pair::KeyPair;
+    use crate::pkh_key_map::PkhKeyMap;
+    use crate::script::Script;
+    use crate::tx_builder::TxBuilder;
+    use crate::tx_in::TxIn;
+    use crate::tx_out::TxOut;
+    use crate::tx_out_bn_map::TxOutBnMap;
+    use crate::tx_signer::TxSigner;
+
+    #[test]
+    fn test_sort_parents_first() {
+        let parent = Tx::new(0, vec![], vec![TxOut::new(1, Script::from_empty())], 0);
+        let input = TxIn::new(parent.id(), 0, Script::from_empty(), 0);
+        let child = Tx::new(0, vec![input], vec![], 0);
+        let input = TxIn::new(child.id(), 0, Script::from_empty(), 0);
+        let grandchild = Tx::new(0, vec![input], vec![], 1);
+        let other = Tx::new(0, vec![], vec![], 2);
+
+        let txs = vec![
+            grandchild.clone(),
+            other.clone(),
+            child.clone(),
+            parent.clone(),
+        ];
+        let sorted: Vec<_> = BlockTemplate::sort_parents_first(txs)
+            .iter()
+            .map(|tx| tx.id())
+            .collect();
+        assert_eq!(
+            sorted,
+            vec![parent.id(), child.id(), grandchild.id(), other.id()]
+        );
+    }
+
+    #[test]
+    fn test_from_mempool() {
+        let key = KeyPair::from_random();
+        let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec());
+        let mut pkh_key_map = PkhKeyMap::new();
+        pkh_key_map.add(key, &pkh.buf);
+        let script = Script::from_pkh_output(&pkh.buf);
+
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, script.clone()), 0);
+        let lch = HeaderChain::new();
+        let mut mempool = Mempool::new(&tx_out_bn_map, 0);
+
+        let mut tx_builder = TxBuilder::new(&tx_out_bn_map, script.clone(), 0);
+        tx_builder.add_output(TxOut::new(60, Script::from_pkh_output(&[2; 32])));
+        let tx = tx_builder.build().unwrap();
+        let tx = TxSigner::new(tx, &tx_out_bn_map, &pkh_key_map, 0)
+            .sign()
+            .unwrap();
+        mempool.add(tx.clone()).unwrap();
+
+        let domain = "example.com".to_string();
+        let template = BlockTemplate::from_mempool(&lch, &mempool, &domain, &pkh, 0).unwrap();
+        assert_eq!(template.txs.len(), 2);
+        assert!(template.txs[0].is_coinbase());
+        assert_eq!(template.txs[1].id(), tx.id());
+        assert_eq!(template.header.block_num, 0);
+        assert_eq!(template.header.merkle_root, template.merkle_txs.root);
+
+        let mut block_verifier =
+            BlockVerifier::new(template.to_block(), tx_out_bn_map.clone(), &lch);
+        assert!(block_verifier.merkle_root_is_valid());
+        assert!(block_verifier.txs_are_valid());
+
+        let template =
+            BlockTemplate::from_mempool_canonical(&lch, &mempool, &domain, &pkh, 0).unwrap();
+        let mut block_verifier = BlockVerifier::new(template.to_block(), tx_out_bn_map, &lch);
+        block_verifier.set_canonical_order(true);
+        assert!(block_verifier.merkle_root_is_valid());
+        assert!(block_verifier.txs_are_valid());
+
+        let stale_mempool = Mempool::new(&TxOutBnMap::new(), 1);
+        assert!(BlockTemplate::from_mempool(&lch, &stale_mempool, &domain, &pkh, 0).is_err());
+    }
+}
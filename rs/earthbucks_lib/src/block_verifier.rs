@@ -2,14 +2,33 @@ use crate::block::Block;
 use crate::domain::Domain;
 use crate::header::Header;
 use crate::header_chain::HeaderChain;
+use crate::merkle_proof::MerkleProof;
 use crate::merkle_txs::MerkleTxs;
+use crate::sig_cache::SigCache;
 use crate::tx_out_bn_map::TxOutBnMap;
 use crate::tx_verifier::TxVerifier;
+use std::sync::Arc;
+
+// why a block was rejected. a mutated merkle tree is reported apart from the
+// other reasons: the txs hash to the merkle root of the header, but they are
+// not necessarily the txs the block was mined with, so a mutated block must
+// not cause its block id to be marked invalid. the original txs may still
+// arrive from another peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockRejection {
+    InvalidHeader,
+    InvalidMerkleRoot,
+    MutatedMerkleTree,
+    InvalidTxs,
+}
 
 pub struct BlockVerifier<'a> {
     pub block: Block,
     pub tx_out_bn_map: TxOutBnMap, // from earlier blocks
     pub lch: &'a HeaderChain,      // longest chain
+    pub num_threads: usize,        // 1 means verify txs sequentially
+    pub canonical_order: bool,     // require txs after the coinbase sorted by id
+    pub sig_cache: Option<Arc<SigCache>>,
 }
 
 impl<'a> BlockVerifier<'a> {
@@ -18,9 +37,32 @@ impl<'a> BlockVerifier<'a> {
             block,
             tx_out_bn_map,
             lch,
+            num_threads: 1,
+            canonical_order: false,
+            sig_cache: None,
         }
     }
 
+    // signatures found in sig_cache, e.g. because the mempool already
+    // verified them, are not verified again
+    pub fn set_sig_cache(&mut self, sig_cache: Arc<SigCache>) {
+        self.sig_cache = Some(sig_cache);
+    }
+
+    // with canonical ordering, the txs after the coinbase must be sorted by
+    // id. a tx may then spend the output of any other tx in the block, not
+    // only an earlier one: the outputs of all txs are added first, and then
+    // the inputs of all txs are spent.
+    pub fn set_canonical_order(&mut self, canonical_order: bool) {
+        self.canonical_order = canonical_order;
+    }
+
+    // verify the txs of the block on num_threads threads. the result is the
+    // same as verifying sequentially.
+    pub fn set_num_threads(&mut self, num_threads: usize) {
+        self.num_threads = num_threads.max(1);
+    }
+
     pub fn header_is_valid_at(&mut self, timestamp: u64) -> bool {
         let header = &self.block.header;
         let lch = &self.lch;
@@ -28,11 +70,19 @@ impl<'a> BlockVerifier<'a> {
     }
 
     pub fn merkle_root_is_valid(&self) -> bool {
-        let txs = &self.block.txs;
         let merkle_root = self.block.header.merkle_root;
-        // TODO: Eliminate clone of txs
-        let merkle_txs = MerkleTxs::new(txs.clone());
-        merkle_txs.root == merkle_root
+        MerkleTxs::root_from_txs(&self.block.txs, self.num_threads) == merkle_root
+    }
+
+    // true if the tx list may have been mutated without changing the merkle
+    // root: a tx id appears twice, or two siblings in the tree are identical
+    pub fn merkle_tree_is_mutated(&self) -> bool {
+        !self.block.txs.is_empty() && self.merkle_root_and_mutation().1
+    }
+
+    fn merkle_root_and_mutation(&self) -> ([u8; 32], bool) {
+        let tx_ids = MerkleTxs::tx_ids(&self.block.txs, self.num_threads);
+        MerkleProof::root_and_mutation_from_hashed_datas(tx_ids, self.num_threads)
     }
 
     pub fn has_valid_coinbase(&self) -> bool {
@@ -96,6 +146,18 @@ impl<'a> BlockVerifier<'a> {
         if !self.has_valid_coinbase() {
             return false;
         }
+        if self.canonical_order {
+            if !self.txs_are_canonically_ordered() {
+                return false;
+            }
+            let block_num = self.block.header.block_num;
+            for tx in &self.block.txs[1..] {
+                self.tx_out_bn_map.add_tx_outputs(tx, block_num);
+            }
+        }
+        if self.num_threads > 1 {
+            return self.txs_are_valid_parallel();
+        }
         let txs = &self.block.txs[1..];
         // iterate through all transactions except the first (coinbase tx)
         // verify with verifier
@@ -103,13 +165,18 @@ impl<'a> BlockVerifier<'a> {
         // if valid, add outputs to tx_output_map and remove used outputs
         for tx in txs {
             let mut tx_verifier =
-                TxVerifier::new(tx.clone(), &self.tx_out_bn_map, self.block.header.block_num);
+                TxVerifier::new(tx, &self.tx_out_bn_map, self.block.header.block_num);
+            if let Some(sig_cache) = &self.sig_cache {
+                tx_verifier.set_sig_cache(sig_cache);
+            }
             if !tx_verifier.verify() {
                 return false;
             }
             let header = &self.block.header;
             let block_num = header.block_num;
-            self.tx_out_bn_map.add_tx_outputs(tx, block_num);
+            if !self.canonical_order {
+                self.tx_out_bn_map.add_tx_outputs(tx, block_num);
+            }
             // remove used outputs to prevent double spending
             for tx_input in &tx.inputs {
                 self.tx_out_bn_map
@@ -119,20 +186,112 @@ impl<'a> BlockVerifier<'a> {
         true
     }
 
+    // the txs after the coinbase are in strictly increasing order of id. this
+    // also rules out the same tx appearing twice.
+    pub fn txs_are_canonically_ordered(&self) -> bool {
+        let tx_ids: Vec<[u8; 32]> = self.block.txs.iter().skip(1).map(|tx| tx.id()).collect();
+        tx_ids.windows(2).all(|pair| pair[0] < pair[1])
+    }
+
+    // verifying a tx only needs the outputs it spends. those are resolved
+    // first, in block order, so that a tx can spend the output of an earlier
+    // tx in the same block (or of any tx in the block, with canonical
+    // ordering, whose outputs are already in the map). the expensive part,
+    // the scripts, then runs on several threads, each tx against its own
+    // small map of spent outputs.
+    fn txs_are_valid_parallel(&mut self) -> bool {
+        let block_num = self.block.header.block_num;
+        let txs = &self.block.txs[1..];
+        let mut tx_out_bn_map = self.tx_out_bn_map.clone();
+        let mut spent_tx_out_bn_maps = Vec::with_capacity(txs.len());
+        for tx in txs {
+            let mut spent_tx_out_bn_map = TxOutBnMap::new();
+            for tx_input in &tx.inputs {
+                let tx_id = &tx_input.input_tx_id;
+                let tx_out_num = tx_input.input_tx_out_num;
+                match tx_out_bn_map.get(tx_id, tx_out_num) {
+                    Some(tx_out_bn) => {
+                        spent_tx_out_bn_map.add(
+                            tx_id,
+                            tx_out_num,
+                            tx_out_bn.tx_out.clone(),
+                            tx_out_bn.block_num,
+                        );
+                    }
+                    // the output does not exist or was spent earlier in the
+                    // block (or earlier in this tx)
+                    None => return false,
+                }
+                tx_out_bn_map.remove(tx_id, tx_out_num);
+            }
+            if !self.canonical_order {
+                tx_out_bn_map.add_tx_outputs(tx, block_num);
+            }
+            spent_tx_out_bn_maps.push(spent_tx_out_bn_map);
+        }
+
+        let chunk_size = txs.len().div_ceil(self.num_threads).max(1);
+        let sig_cache = self.sig_cache.as_deref();
+        let all_valid = std::thread::scope(|scope| {
+            let handles: Vec<_> = txs
+                .chunks(chunk_size)
+                .zip(spent_tx_out_bn_maps.chunks(chunk_size))
+                .map(|(txs, spent_tx_out_bn_maps)| {
+                    scope.spawn(move || {
+                        txs.iter()
+                            .zip(spent_tx_out_bn_maps)
+                            .all(|(tx, spent_tx_out_bn_map)| {
+                                let mut tx_verifier =
+                                    TxVerifier::new(tx, spent_tx_out_bn_map, block_num);
+                                if let Some(sig_cache) = sig_cache {
+                                    tx_verifier.set_sig_cache(sig_cache);
+                                }
+                                tx_verifier.verify()
+                            })
+                    })
+                })
+                .collect();
+            // a panic in a verifier thread is a bug, not an invalid block, so
+            // it is propagated
+            let results: Vec<bool> = handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect();
+            !results.contains(&false)
+        });
+        if !all_valid {
+            return false;
+        }
+        self.tx_out_bn_map = tx_out_bn_map;
+        true
+    }
+
     pub fn is_valid_at(&mut self, timestamp: u64) -> bool {
+        self.verify_at(timestamp).is_ok()
+    }
+
+    // like is_valid_at, with the reason the block is rejected
+    pub fn verify_at(&mut self, timestamp: u64) -> Result<(), BlockRejection> {
         if timestamp < self.block.header.timestamp {
-            return false;
+            return Err(BlockRejection::InvalidHeader);
         }
         if !self.header_is_valid_at(timestamp) {
-            return false;
+            return Err(BlockRejection::InvalidHeader);
         }
-        if !self.merkle_root_is_valid() {
-            return false;
+        if self.block.txs.is_empty() {
+            return Err(BlockRejection::InvalidTxs);
+        }
+        let (merkle_root, mutated) = self.merkle_root_and_mutation();
+        if merkle_root != self.block.header.merkle_root {
+            return Err(BlockRejection::InvalidMerkleRoot);
+        }
+        if mutated {
+            return Err(BlockRejection::MutatedMerkleTree);
         }
         if !self.txs_are_valid() {
-            return false;
+            return Err(BlockRejection::InvalidTxs);
         }
-        true
+        Ok(())
     }
 
     pub fn is_valid_now(&mut self) -> bool {
@@ -140,3 +299,213 @@ impl<'a> BlockVerifier<'a> {
         self.is_valid_at(timestamp)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_pair::KeyPair;
+    use crate::mempool::Mempool;
+    use crate::pkh::Pkh;
+    use crate::pkh_key_map::PkhKeyMap;
+    use crate::script::Script;
+    use crate::tx_builder::TxBuilder;
+    use crate::tx_out::TxOut;
+    use crate::tx_signature::TxSignature;
+    use crate::tx_signer::TxSigner;
+
+    // a block with the coinbase followed by txs. each tx spends the change
+    // of the previous tx, and the first tx spends the output in the returned
+    // map.
+    fn setup(n_txs: usize) -> (Block, TxOutBnMap, HeaderChain) {
+        let key = KeyPair::from_random();
+        let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec());
+        let mut pkh_key_map = PkhKeyMap::new();
+        pkh_key_map.add(key, &pkh.buf);
+        let script = Script::from_pkh_output(&pkh.buf);
+
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(1000, script.clone()), 0);
+        let lch = HeaderChain::new();
+        let domain = "example.com".to_string();
+        let mut txs = vec![lch.get_next_coinbase_tx(&pkh, &domain)];
+        let mut unspent = tx_out_bn_map.clone();
+        for _ in 0..n_txs {
+            let mut tx_builder = TxBuilder::new(&unspent, script.clone(), 0);
+            tx_builder.add_output(TxOut::new(1, Script::from_pkh_output(&[2; 32])));
+            let tx = tx_builder.build().unwrap();
+            let tx = TxSigner::new(tx, &unspent, &pkh_key_map, 0).sign().unwrap();
+            unspent = TxOutBnMap::new();
+            unspent.add(&tx.id(), 1, tx.outputs[1].clone(), 0);
+            txs.push(tx);
+        }
+        let header = lch.get_next_header([0; 32], 0).unwrap();
+        (Block::new(header, txs), tx_out_bn_map, lch)
+    }
+
+    #[test]
+    fn test_mutated_merkle_tree() {
+        let (mut block, tx_out_bn_map, lch) = setup(2);
+        block.header.merkle_root = MerkleTxs::new(block.txs.clone()).root;
+        let mut block_verifier = BlockVerifier::new(block.clone(), tx_out_bn_map.clone(), &lch);
+        assert!(!block_verifier.merkle_tree_is_mutated());
+        assert_eq!(block_verifier.verify_at(0), Ok(()));
+
+        // three txs are padded to four by repeating the last one, so the
+        // list with the last tx twice has the same merkle root
+        let mut mutated_block = block.clone();
+        mutated_block.txs.push(block.txs[2].clone());
+        let mut block_verifier = BlockVerifier::new(mutated_block, tx_out_bn_map.clone(), &lch);
+        assert!(block_verifier.merkle_root_is_valid());
+        assert!(block_verifier.merkle_tree_is_mutated());
+        assert_eq!(
+            block_verifier.verify_at(0),
+            Err(BlockRejection::MutatedMerkleTree)
+        );
+
+        // a tx id twice, not at the end, changes the root
+        let mut mutated_block = block.clone();
+        mutated_block.txs.insert(1, block.txs[1].clone());
+        let mut block_verifier = BlockVerifier::new(mutated_block, tx_out_bn_map.clone(), &lch);
+        assert!(block_verifier.merkle_tree_is_mutated());
+        assert_eq!(
+            block_verifier.verify_at(0),
+            Err(BlockRejection::InvalidMerkleRoot)
+        );
+
+        let mut block_verifier = BlockVerifier::new(block, tx_out_bn_map, &lch);
+        block_verifier.set_num_threads(2);
+        block_verifier.block.txs.swap(1, 2);
+        assert!(!block_verifier.merkle_tree_is_mutated());
+        assert_eq!(
+            block_verifier.verify_at(0),
+            Err(BlockRejection::InvalidMerkleRoot)
+        );
+    }
+
+    #[test]
+    fn test_txs_are_valid_parallel_matches_sequential() {
+        let (block, tx_out_bn_map, lch) = setup(5);
+
+        let mut sequential = BlockVerifier::new(block.clone(), tx_out_bn_map.clone(), &lch);
+        assert!(sequential.txs_are_valid());
+        for num_threads in [2, 3, 8] {
+            let mut parallel = BlockVerifier::new(block.clone(), tx_out_bn_map.clone(), &lch);
+            parallel.set_num_threads(num_threads);
+            assert!(parallel.txs_are_valid());
+            let mut names: Vec<_> = parallel.tx_out_bn_map.keys().collect();
+            let mut expected_names: Vec<_> = sequential.tx_out_bn_map.keys().collect();
+            names.sort();
+            expected_names.sort();
+            assert_eq!(names, expected_names);
+        }
+    }
+
+    #[test]
+    fn test_txs_are_invalid_parallel() {
+        // a child placed before its parent spends an output that does not
+        // exist yet
+        let (mut block, tx_out_bn_map, lch) = setup(3);
+        block.txs.swap(1, 2);
+        let mut sequential = BlockVerifier::new(block.clone(), tx_out_bn_map.clone(), &lch);
+        assert!(!sequential.txs_are_valid());
+        let mut parallel = BlockVerifier::new(block, tx_out_bn_map.clone(), &lch);
+        parallel.set_num_threads(4);
+        assert!(!parallel.txs_are_valid());
+
+        // the same tx twice double spends
+        let (mut block, tx_out_bn_map, lch) = setup(2);
+        block.txs.push(block.txs[1].clone());
+        let mut parallel = BlockVerifier::new(block, tx_out_bn_map.clone(), &lch);
+        parallel.set_num_threads(4);
+        assert!(!parallel.txs_are_valid());
+
+        // a bad signature is caught on a worker thread
+        let (mut block, tx_out_bn_map, lch) = setup(4);
+        block.txs[3].inputs[0].script.chunks[0].buffer = Some(vec![0; 65]);
+        let mut parallel = BlockVerifier::new(block, tx_out_bn_map, &lch);
+        parallel.set_num_threads(2);
+        assert!(!parallel.txs_are_valid());
+    }
+
+    #[test]
+    fn test_canonical_order() {
+        let (mut block, tx_out_bn_map, lch) = setup(5);
+        block.txs[1..].sort_by_cached_key(|tx| tx.id());
+        for num_threads in [1, 3] {
+            let mut block_verifier = BlockVerifier::new(block.clone(), tx_out_bn_map.clone(), &lch);
+            block_verifier.set_canonical_order(true);
+            block_verifier.set_num_threads(num_threads);
+            assert!(block_verifier.txs_are_canonically_ordered());
+            assert!(block_verifier.txs_are_valid());
+            // only the change of the last tx in the chain and the payments
+            // are left
+            assert_eq!(block_verifier.tx_out_bn_map.len(), 6);
+        }
+
+        block.txs[1..].reverse();
+        let mut block_verifier = BlockVerifier::new(block.clone(), tx_out_bn_map.clone(), &lch);
+        block_verifier.set_canonical_order(true);
+        assert!(!block_verifier.txs_are_canonically_ordered());
+        assert!(!block_verifier.txs_are_valid());
+
+        // a tx may not appear twice
+        let (mut block, tx_out_bn_map, lch) = setup(1);
+        block.txs.push(block.txs[1].clone());
+        let mut block_verifier = BlockVerifier::new(block, tx_out_bn_map, &lch);
+        block_verifier.set_canonical_order(true);
+        assert!(!block_verifier.txs_are_valid());
+    }
+
+    #[test]
+    fn test_sig_cache_shared_with_mempool() {
+        let (block, tx_out_bn_map, lch) = setup(3);
+        let sig_cache = Arc::new(SigCache::default());
+        let mut mempool = Mempool::new(&tx_out_bn_map, 0);
+        mempool.set_sig_cache(sig_cache.clone());
+        for tx in &block.txs[1..] {
+            mempool.add(tx.clone()).unwrap();
+        }
+        assert_eq!(sig_cache.len(), 3);
+
+        for num_threads in [1, 2] {
+            let mut block_verifier = BlockVerifier::new(block.clone(), tx_out_bn_map.clone(), &lch);
+            block_verifier.set_sig_cache(sig_cache.clone());
+            block_verifier.set_num_threads(num_threads);
+            assert!(block_verifier.txs_are_valid());
+        }
+        assert_eq!(sig_cache.len(), 3);
+    }
+
+    #[test]
+    fn test_sig_cache_skips_ecdsa() {
+        // a signature in the cache is trusted, so a cache entry for a bad
+        // signature makes it pass. this shows the cache is consulted.
+        let (mut block, tx_out_bn_map, lch) = setup(1);
+        let input_script = &mut block.txs[1].inputs[0].script;
+        let mut sig_buf = input_script.chunks[0].buffer.clone().unwrap();
+        sig_buf[10] ^= 1;
+        input_script.chunks[0].buffer = Some(sig_buf.clone());
+        let pub_key: [u8; 33] = input_script.chunks[1]
+            .buffer
+            .clone()
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+        let mut block_verifier = BlockVerifier::new(block.clone(), tx_out_bn_map.clone(), &lch);
+        assert!(!block_verifier.txs_are_valid());
+
+        let tx_out = &tx_out_bn_map.get(&[1; 32], 0).unwrap().tx_out;
+        let sighash = block.txs[1].sighash_no_cache(
+            0,
+            tx_out.script.to_buf(),
+            tx_out.value,
+            TxSignature::SIGHASH_ALL,
+        );
+        let sig_cache = Arc::new(SigCache::default());
+        sig_cache.insert(&sighash, &pub_key, &sig_buf.try_into().unwrap());
+        let mut block_verifier = BlockVerifier::new(block, tx_out_bn_map, &lch);
+        block_verifier.set_sig_cache(sig_cache);
+        assert!(block_verifier.txs_are_valid());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::error::EbxError;
+use crate::script::Script;
+use crate::script_template::ScriptTemplates;
+use crate::tx::Tx;
+use crate::tx_in::TxIn;
+use crate::tx_out::TxOut;
+use crate::tx_out_bn_map::TxOutBnMap;
+use crate::tx_verifier::TxVerifier;
+
+// expired pkhx and pkhxr outputs can be spent by anyone without a signature.
+// mines are expected to collect them, which both cleans up the utxo set and
+// pays the mine. this builder finds every output in the map that is expired at
+// the working block number and spends them to the mine's pkh in batches of at
+// most max_inputs_per_tx inputs.
+pub struct ExpiredTxBuilder {
+    tx_out_bn_map: TxOutBnMap,
+    output_script: Script,
+    working_block_num: u32,
+    max_inputs_per_tx: usize,
+    script_templates: ScriptTemplates,
+}
+
+impl ExpiredTxBuilder {
+    pub const DEFAULT_MAX_INPUTS_PER_TX: usize = 1000;
+
+    pub fn new(
+        tx_out_bn_map: &TxOutBnMap,
+        mine_pkh: &[u8; 32],
+        working_block_num: u32,
+        max_inputs_per_tx: usize,
+    ) -> Self {
+        Self {
+            tx_out_bn_map: tx_out_bn_map.clone(),
+            output_script: Script::from_pkh_output(mine_pkh),
+            working_block_num,
+            max_inputs_per_tx,
+            script_templates: ScriptTemplates::from_standard(),
+        }
+    }
+
+    // the templates used to recognize expiring outputs. defaults to the
+    // standard output types.
+    pub fn set_script_templates(&mut self, script_templates: ScriptTemplates) {
+        self.script_templates = script_templates;
+    }
+
+    // expired inputs in the order of TxOutBnMap::sorted, the same as
+    // TxBuilder, so that the batches are deterministic
+    pub fn expired_inputs(&self) -> Vec<(TxIn, u64)> {
+        let mut inputs = Vec::new();
+        for (tx_out_id, tx_out_bn) in self.tx_out_bn_map.sorted() {
+            let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(tx_out_id).try_into().unwrap();
+            let tx_out_num = TxOutBnMap::name_to_tx_out_num(tx_out_id);
+            let script = &tx_out_bn.tx_out.script;
+            let expired_input = self.script_templates.find(script).and_then(|template| {
+                template.expired_input(script, tx_out_bn.block_num, self.working_block_num)
+            });
+            if let Some((input_script, lock_rel)) = expired_input {
+                let tx_in = TxIn::new(tx_id, tx_out_num, input_script, lock_rel);
+                inputs.push((tx_in, tx_out_bn.tx_out.value));
+            }
+        }
+        inputs
+    }
+
+    pub fn build(&self) -> Result<Vec<Tx>, EbxError> {
+        if self.max_inputs_per_tx == 0 {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "max inputs per tx must be at least 1".to_string(),
+            });
+        }
+        let mut txs = Vec::new();
+        for batch in self.expired_inputs().chunks(self.max_inputs_per_tx) {
+            let inputs: Vec<TxIn> = batch.iter().map(|(tx_in, _)| tx_in.clone()).collect();
+            let value: u64 = batch.iter().map(|(_, value)| value).sum();
+            let outputs = vec![TxOut::new(value, self.output_script.clone())];
+            let tx = Tx::new(0, inputs, outputs, 0);
+
+            let mut tx_verifier = TxVerifier::new(&tx, &self.tx_out_bn_map, self.working_block_num);
+            if !tx_verifier.verify() {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "expired tx failed verification".to_string(),
+                });
+            }
+            txs.push(tx);
+        }
+        Ok(txs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key_pair::KeyPair;
+    use crate::pkh::Pkh;
+
+    #[test]
+    fn test_build_spends_expired_outputs_in_batches() {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let pkh = Pkh::from_pub_key_buffer(KeyPair::from_random().pub_key.buf.to_vec());
+        let working_block_num = Script::PKHX_90D_LOCK_REL + 10;
+
+        // expired
+        let script = Script::from_pkhx_1h_output(&pkh.buf);
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(1, script.clone()), 0);
+        tx_out_bn_map.add(&[1; 32], 1, TxOut::new(1, script), 0);
+        let script = Script::from_pkhx_90d_output(&pkh.buf);
+        tx_out_bn_map.add(&[2; 32], 0, TxOut::new(4, script), 10);
+        let script = Script::from_pkhxr_1h_40m_output(&pkh.buf, &pkh.buf);
+        tx_out_bn_map.add(&[3; 32], 0, TxOut::new(8, script), 1);
+        let script = Script::from_pkhxr_90d_60d_output(&pkh.buf, &pkh.buf);
+        tx_out_bn_map.add(&[4; 32], 0, TxOut::new(16, script), 2);
+        // not expired
+        let script = Script::from_pkhx_90d_output(&pkh.buf);
+        tx_out_bn_map.add(&[5; 32], 0, TxOut::new(32, script), 11);
+        let script = Script::from_pkhx_1h_output(&pkh.buf);
+        tx_out_bn_map.add(&[6; 32], 0, TxOut::new(64, script), working_block_num - 1);
+        // never expires
+        let script = Script::from_pkh_output(&pkh.buf);
+        tx_out_bn_map.add(&[7; 32], 0, TxOut::new(128, script), 0);
+
+        let mine_pkh = Pkh::from_pub_key_buffer(KeyPair::from_random().pub_key.buf.to_vec());
+        let builder = ExpiredTxBuilder::new(&tx_out_bn_map, &mine_pkh.buf, working_block_num, 2);
+        assert_eq!(builder.expired_inputs().len(), 5);
+
+        let txs = builder.build().unwrap();
+        assert_eq!(txs.len(), 3);
+        assert_eq!(txs[0].inputs.len(), 2);
+        assert_eq!(txs[1].inputs.len(), 2);
+        assert_eq!(txs[2].inputs.len(), 1);
+        let total: u64 = txs.iter().map(|tx| tx.outputs[0].value).sum();
+        assert_eq!(total, 1 + 1 + 4 + 8 + 16);
+        for tx in &txs {
+            assert_eq!(tx.outputs.len(), 1);
+            assert_eq!(tx.outputs[0].script, Script::from_pkh_output(&mine_pkh.buf));
+            let mut tx_verifier = TxVerifier::new(tx, &tx_out_bn_map, working_block_num);
+            assert!(tx_verifier.verify());
+        }
+    }
+
+    #[test]
+    fn test_build_with_nothing_expired() {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let script = Script::from_pkhx_90d_output(&[0; 32]);
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(1, script), 0);
+        let builder = ExpiredTxBuilder::new(&tx_out_bn_map, &[0; 32], 1, 10);
+        assert!(builder.build().unwrap().is_empty());
+    }
+}
@@ -131,26 +131,41 @@ impl Header {
     }
 
     pub fn is_valid_in_lch(&self, lch: &[Header]) -> bool {
+        self.is_valid_after(lch.last(), lch.len() as u32, |timestamp| {
+            Header::new_target_from_lch(lch, timestamp)
+        })
+    }
+
+    // the checks of is_valid_in_lch, for a chain of chain_len headers ending
+    // in tip. new_target gives the target of the next header at a timestamp.
+    pub fn is_valid_after(
+        &self,
+        tip: Option<&Header>,
+        chain_len: u32,
+        new_target: impl FnOnce(u64) -> Result<u256, EbxError>,
+    ) -> bool {
         if !self.is_version_valid() {
             return false;
         }
         if self.block_num == 0 {
             return self.is_genesis();
         }
-        if lch.is_empty() {
-            return false;
-        }
-        if self.block_num != lch.len() as u32 {
+        let tip = match tip {
+            Some(tip) => tip,
+            None => return false,
+        };
+        if self.block_num != chain_len {
             return false;
         }
-        if self.prev_block_id != lch.last().unwrap().id() {
+        if self.prev_block_id != tip.id() {
             return false;
         }
-        if self.timestamp <= lch.last().unwrap().timestamp {
+        if self.timestamp <= tip.timestamp {
             return false;
         }
-        if !self.is_target_valid(lch) {
-            return false;
+        match new_target(self.timestamp) {
+            Ok(target) if target == self.target => {}
+            _ => return false,
         }
         if !self.is_id_valid() {
             return false;
@@ -206,20 +221,26 @@ impl Header {
     }
 
     pub fn from_lch(lch: &[Header], new_timestamp: u64) -> Result<Self, EbxError> {
-        if lch.is_empty() {
-            return Ok(Header::from_genesis(new_timestamp));
+        match lch.last() {
+            Some(prev_block) => {
+                let new_target = Header::new_target_from_lch(lch, new_timestamp)?;
+                Ok(Header::from_prev(prev_block, new_target, new_timestamp))
+            }
+            None => Ok(Header::from_genesis(new_timestamp)),
         }
-        let new_target = Header::new_target_from_lch(lch, new_timestamp)?;
-        let prev_block = lch.last().unwrap();
+    }
+
+    // the header after prev_block, valid except for PoW and the merkle root
+    pub fn from_prev(prev_block: &Header, new_target: u256, new_timestamp: u64) -> Self {
         let prev_block_id = prev_block.id();
-        let block_num = lch.len() as u32;
+        let block_num = prev_block.block_num + 1;
         let timestamp = new_timestamp;
         let nonce = u256::from(0u8);
         let work_ser_algo = prev_block.work_ser_algo;
         let work_ser_hash = [0u8; 32];
         let work_par_algo = prev_block.work_par_algo;
         let work_par_hash = [0u8; 32];
-        Ok(Self {
+        Self {
             version: 0,
             prev_block_id,
             merkle_root: [0u8; 32],
@@ -231,29 +252,34 @@ impl Header {
             work_ser_hash,
             work_par_algo,
             work_par_hash,
-        })
+        }
     }
 
     pub fn new_target_from_lch(lch: &[Header], new_timestamp: u64) -> Result<u256, EbxError> {
-        let adjh: Vec<Header> = if lch.len() > Header::BLOCKS_PER_TARGET_ADJ_PERIOD as usize {
-            lch[lch.len() - Header::BLOCKS_PER_TARGET_ADJ_PERIOD as usize..].to_vec()
-        } else {
-            lch.to_vec()
+        let start = lch
+            .len()
+            .saturating_sub(Header::BLOCKS_PER_TARGET_ADJ_PERIOD as usize);
+        let adjh = &lch[start..];
+        let target_sum: BigUint = adjh.iter().map(|header| header.target_num()).sum();
+        Header::new_target_from_sum(adjh.first(), target_sum, adjh.len() as u32, new_timestamp)
+    }
+
+    // the target after an adjustment period of len headers, starting with
+    // first_header, whose targets add up to target_sum
+    pub fn new_target_from_sum(
+        first_header: Option<&Header>,
+        target_sum: BigUint,
+        len: u32,
+        new_timestamp: u64,
+    ) -> Result<u256, EbxError> {
+        let first_header = match first_header {
+            Some(first_header) => first_header,
+            None => {
+                return Ok(BufReader::new(Header::MAX_TARGET_BYTES.to_vec())
+                    .read_u256_be()
+                    .unwrap())
+            }
         };
-        let len: u32 = adjh.len() as u32;
-        if len == 0 {
-            return Ok(BufReader::new(Header::MAX_TARGET_BYTES.to_vec())
-                .read_u256_be()
-                .unwrap());
-        }
-        let first_header = adjh[0].clone();
-        let mut targets: Vec<BigUint> = Vec::new();
-        for header in adjh {
-            let target =
-                BigUint::from_bytes_be(&BufWriter::new().write_u256_be(header.target).to_buf());
-            targets.push(target);
-        }
-        let target_sum: BigUint = targets.iter().sum();
         if new_timestamp <= first_header.timestamp {
             return Err(EbxError::GenericError {
                 source: None,
@@ -265,6 +291,11 @@ impl Header {
         Ok(new_target)
     }
 
+    // the target as a number that can be summed without overflow
+    pub fn target_num(&self) -> BigUint {
+        BigUint::from_bytes_be(&BufWriter::new().write_u256_be(self.target).to_buf())
+    }
+
     pub fn new_target_from_old_targets(target_sum: BigUint, real_time_diff: u64, len: u32) -> u256 {
         // - target_sum is sum of all targets in the adjustment period
         // - real_time_diff is the time difference between the first block in
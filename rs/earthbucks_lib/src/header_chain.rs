@@ -1,46 +1,264 @@
 use crate::error::EbxError;
+use crate::hash::blake3_hash;
 use crate::header::Header;
+use crate::numbers::u256;
 use crate::pkh::Pkh;
 use crate::script::Script;
 use crate::script_chunk::ScriptChunk;
 use crate::tx::Tx;
+use num_bigint::BigUint;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
 
 // add Default
 #[derive(Default, Clone)]
 pub struct HeaderChain {
-    pub headers: Vec<Header>,
+    headers: Vec<Header>,
+    // the sum of the targets of the target adjustment period, kept up to date
+    // by add and truncate so the next target is found without going over the
+    // period again
+    target_sum: BigUint,
 }
 
 impl HeaderChain {
     pub const LENGTH_TARGET_ADJ_PERIOD: u32 = Header::BLOCKS_PER_TARGET_ADJ_PERIOD;
     pub const LENGTH_EXPIRY_PERIOD: u32 = Script::PKHXR_90D_60D_X_LOCK_REL;
     pub const LENGTH_SAFETY_PERIOD: u32 = HeaderChain::LENGTH_EXPIRY_PERIOD * 2;
+    // a saved header followed by its blake3 checksum
+    const RECORD_SIZE: usize = Header::SIZE + 32;
 
     pub fn new() -> Self {
         Self {
             headers: Vec::new(),
+            target_sum: BigUint::default(),
         }
     }
 
     pub fn add(&mut self, header: Header) -> &mut Self {
+        self.target_sum += header.target_num();
         self.headers.push(header);
+        let adj_period = Self::LENGTH_TARGET_ADJ_PERIOD as usize;
+        if self.headers.len() > adj_period {
+            // the header that just left the target adjustment period
+            let old = &self.headers[self.headers.len() - 1 - adj_period];
+            self.target_sum -= old.target_num();
+        }
         self
     }
 
+    // removes the headers from len on, e.g. for a reorg
+    pub fn truncate(&mut self, len: usize) -> &mut Self {
+        self.headers.truncate(len);
+        self.target_sum = self
+            .adj_headers()
+            .iter()
+            .map(|header| header.target_num())
+            .sum();
+        self
+    }
+
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+
+    // the number of headers, which is the number of the next block
+    pub fn len(&self) -> u32 {
+        self.headers.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub fn get(&self, block_num: u32) -> Option<&Header> {
+        self.headers.get(block_num as usize)
+    }
+
+    // the headers of the target adjustment period
+    fn adj_headers(&self) -> &[Header] {
+        let start = self
+            .headers
+            .len()
+            .saturating_sub(Self::LENGTH_TARGET_ADJ_PERIOD as usize);
+        &self.headers[start..]
+    }
+
+    // the same as Header::new_target_from_lch, using the rolling target sum
+    pub fn new_target(&self, new_timestamp: u64) -> Result<u256, EbxError> {
+        let adjh = self.adj_headers();
+        Header::new_target_from_sum(
+            adjh.first(),
+            self.target_sum.clone(),
+            adjh.len() as u32,
+            new_timestamp,
+        )
+    }
+
     pub fn get_tip(&self) -> Option<&Header> {
         self.headers.last()
     }
 
+    // the block number of the header with block_id. the ids of all headers
+    // but the tip are the prev_block_id of the next header, so only the tip
+    // is hashed.
+    pub fn find_block_num(&self, block_id: &[u8; 32]) -> Option<u32> {
+        let tip = self.headers.last()?;
+        if tip.id() == *block_id {
+            return Some(self.headers.len() as u32 - 1);
+        }
+        self.headers
+            .windows(2)
+            .rposition(|pair| pair[1].prev_block_id == *block_id)
+            .map(|block_num| block_num as u32)
+    }
+
+    fn error(message: String) -> EbxError {
+        EbxError::GenericError {
+            source: None,
+            message,
+        }
+    }
+
+    fn io_error(error: std::io::Error) -> EbxError {
+        Self::error(error.to_string())
+    }
+
+    fn to_record(header: &Header) -> Vec<u8> {
+        let mut record = header.to_buf().to_vec();
+        let checksum = blake3_hash(&record);
+        record.extend_from_slice(&checksum);
+        record
+    }
+
+    // writes the headers to path, replacing the file. the file is the headers
+    // back to back, each followed by its blake3 checksum. it is written to a
+    // temporary file that is then renamed, so a crash leaves either the old
+    // or the new file.
+    pub fn save(&self, path: &Path) -> Result<(), EbxError> {
+        let mut buf = Vec::with_capacity(self.headers.len() * Self::RECORD_SIZE);
+        for header in &self.headers {
+            buf.extend_from_slice(&Self::to_record(header));
+        }
+        let tmp_path = path.with_extension("tmp");
+        let mut file = File::create(&tmp_path).map_err(Self::io_error)?;
+        file.write_all(&buf).map_err(Self::io_error)?;
+        file.sync_all().map_err(Self::io_error)?;
+        fs::rename(&tmp_path, path).map_err(Self::io_error)
+    }
+
+    // brings the file saved at path up to date by appending the headers it
+    // does not have yet. if the chain was reorganized, the file is cut back to
+    // the last header it has in common with the chain first.
+    pub fn save_new_headers(&self, path: &Path) -> Result<(), EbxError> {
+        let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return self.save(path);
+            }
+            Err(error) => return Err(Self::io_error(error)),
+        };
+        let file_len = file.metadata().map_err(Self::io_error)?.len() as usize;
+        let mut n_common = (file_len / Self::RECORD_SIZE).min(self.headers.len());
+        while n_common > 0 {
+            let mut buf = [0u8; Self::RECORD_SIZE];
+            file.seek(SeekFrom::Start(((n_common - 1) * Self::RECORD_SIZE) as u64))
+                .map_err(Self::io_error)?;
+            file.read_exact(&mut buf).map_err(Self::io_error)?;
+            if buf[..] == Self::to_record(&self.headers[n_common - 1])[..] {
+                break;
+            }
+            n_common -= 1;
+        }
+        let n_common_bytes = (n_common * Self::RECORD_SIZE) as u64;
+        file.set_len(n_common_bytes).map_err(Self::io_error)?;
+        file.seek(SeekFrom::Start(n_common_bytes))
+            .map_err(Self::io_error)?;
+        let mut buf = Vec::new();
+        for header in &self.headers[n_common..] {
+            buf.extend_from_slice(&Self::to_record(header));
+        }
+        file.write_all(&buf).map_err(Self::io_error)?;
+        file.sync_data().map_err(Self::io_error)
+    }
+
+    // reads the headers saved at path. a torn header at the end is ignored.
+    // the checksums of all the headers are checked first, then every header
+    // must follow the one before it. the headers after
+    // trusted_checkpoint, a block number and block id, are validated in full,
+    // including proof of work and target. the headers up to the checkpoint
+    // are only checked to be linked, which is much faster, and the header at
+    // the checkpoint must have its id. without a checkpoint every header is
+    // validated in full.
+    pub fn load(
+        path: &Path,
+        trusted_checkpoint: Option<(u32, [u8; 32])>,
+    ) -> Result<Self, EbxError> {
+        let buf = fs::read(path).map_err(Self::io_error)?;
+        for (block_num, record) in buf.chunks_exact(Self::RECORD_SIZE).enumerate() {
+            let (header_buf, checksum) = record.split_at(Header::SIZE);
+            if blake3_hash(header_buf) != checksum {
+                return Err(Self::error(format!(
+                    "invalid header checksum at block {}",
+                    block_num
+                )));
+            }
+        }
+        let mut lch = Self::new();
+        let mut prev_block_id = [0u8; 32];
+        for record in buf.chunks_exact(Self::RECORD_SIZE) {
+            let header = Header::from_buf(record[..Header::SIZE].try_into().unwrap())?;
+            let block_num = lch.len();
+            let trusted = match trusted_checkpoint {
+                Some((checkpoint_num, _)) => block_num <= checkpoint_num,
+                None => false,
+            };
+            let linked = header.block_num == block_num && header.prev_block_id == prev_block_id;
+            if !linked || (!trusted && !lch.new_header_is_valid(&header)) {
+                return Err(Self::error(format!(
+                    "invalid header at block {}",
+                    block_num
+                )));
+            }
+            prev_block_id = header.id();
+            if let Some((checkpoint_num, checkpoint_id)) = trusted_checkpoint {
+                if block_num == checkpoint_num && prev_block_id != checkpoint_id {
+                    return Err(Self::error("header does not match checkpoint".to_string()));
+                }
+            }
+            lch.add(header);
+        }
+        if let Some((checkpoint_num, _)) = trusted_checkpoint {
+            if checkpoint_num as usize >= lch.headers.len() {
+                return Err(Self::error(
+                    "checkpoint is past the saved headers".to_string(),
+                ));
+            }
+        }
+        Ok(lch)
+    }
+
+    // the same as Header::is_valid_in_lch on the whole chain
+    pub fn new_header_is_valid(&self, header: &Header) -> bool {
+        header.is_valid_after(self.get_tip(), self.len(), |timestamp| {
+            self.new_target(timestamp)
+        })
+    }
+
     pub fn new_header_is_valid_at(&self, header: &Header, timestamp: u64) -> bool {
-        header.is_valid_at(&self.headers, timestamp)
+        header.is_timestamp_valid_at(timestamp) && self.new_header_is_valid(header)
     }
 
     pub fn new_header_is_valid_now(&self, header: &Header) -> bool {
-        header.is_valid_now(&self.headers)
+        self.new_header_is_valid_at(header, Header::get_new_timestamp())
     }
 
     pub fn get_next_coinbase_tx(&self, pkh: &Pkh, domain: &String) -> Tx {
-        let building_block_n: u32 = self.headers.len() as u32;
+        Self::coinbase_tx(self.len(), pkh, domain)
+    }
+
+    // the coinbase tx of block building_block_n
+    pub fn coinbase_tx(building_block_n: u32, pkh: &Pkh, domain: &String) -> Tx {
         let domain_buf = domain.as_bytes();
         let script_chunk_domain = ScriptChunk::from_data(domain_buf.to_vec());
         let input_script = Script::new(vec![script_chunk_domain]);
@@ -55,9 +273,12 @@ impl HeaderChain {
         new_timestamp: u64,
     ) -> Result<Header, EbxError> {
         // valid block header, except for PoW
-        let mut block_header: Header = Header::from_lch(&self.headers, new_timestamp)?;
-        block_header.merkle_root = merkle_root;
-        Ok(block_header)
+        let mut header = match self.get_tip() {
+            Some(tip) => Header::from_prev(tip, self.new_target(new_timestamp)?, new_timestamp),
+            None => Header::from_genesis(new_timestamp),
+        };
+        header.merkle_root = merkle_root;
+        Ok(header)
     }
 }
 
@@ -83,7 +304,115 @@ mod tests {
             work_par_hash: [0; 32],
         };
         chain.add(header);
-        assert_eq!(chain.headers.len(), 1);
+        assert_eq!(chain.len(), 1);
+    }
+
+    fn temp_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("earthbucks_headers_{}.dat", rand::random::<u64>()))
+    }
+
+    fn chain(n_headers: u32) -> HeaderChain {
+        let mut lch = HeaderChain::new();
+        for block_num in 0..n_headers {
+            let header = lch
+                .get_next_header([block_num as u8; 32], block_num as u64 * 600_000)
+                .unwrap();
+            lch.add(header);
+        }
+        lch
+    }
+
+    fn ids(lch: &HeaderChain) -> Vec<[u8; 32]> {
+        lch.headers().iter().map(|header| header.id()).collect()
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = temp_path();
+        let lch = chain(5);
+        lch.save(&path).unwrap();
+        assert_eq!(ids(&HeaderChain::load(&path, None).unwrap()), ids(&lch));
+        let checkpoint = Some((2, lch.headers()[2].id()));
+        assert_eq!(
+            ids(&HeaderChain::load(&path, checkpoint).unwrap()),
+            ids(&lch)
+        );
+        assert!(HeaderChain::load(&path, Some((2, [0; 32]))).is_err());
+        assert!(HeaderChain::load(&path, Some((5, [0; 32]))).is_err());
+
+        // a torn header at the end is ignored
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0; 10]).unwrap();
+        assert_eq!(ids(&HeaderChain::load(&path, None).unwrap()), ids(&lch));
+
+        // a flipped bit fails the checksum, even in a trusted header
+        let mut buf = fs::read(&path).unwrap();
+        buf[HeaderChain::RECORD_SIZE + 80] ^= 1;
+        fs::write(&path, &buf).unwrap();
+        assert!(HeaderChain::load(&path, None).is_err());
+        assert!(HeaderChain::load(&path, Some((4, lch.headers()[4].id()))).is_err());
+
+        // a changed header with a matching checksum breaks the link to the
+        // next one
+        let mut buf = fs::read(&path).unwrap();
+        buf[HeaderChain::RECORD_SIZE + 80] ^= 1;
+        let mut header_buf = lch.headers()[3].to_buf();
+        header_buf[40] ^= 1;
+        let header = Header::from_buf(header_buf).unwrap();
+        let start = 3 * HeaderChain::RECORD_SIZE;
+        buf[start..start + HeaderChain::RECORD_SIZE]
+            .copy_from_slice(&HeaderChain::to_record(&header));
+        fs::write(&path, &buf).unwrap();
+        assert!(HeaderChain::load(&path, None).is_err());
+        assert!(HeaderChain::load(&path, Some((4, lch.headers()[4].id()))).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_new_target_after_truncate() {
+        let mut lch = HeaderChain::new();
+        // blocks faster than intended, so the targets differ
+        for block_num in 0..2030u32 {
+            let header = lch
+                .get_next_header([0; 32], block_num as u64 * 400_000)
+                .unwrap();
+            lch.add(header);
+        }
+        for len in [2030, 2020, 2016, 2000] {
+            lch.truncate(len);
+            let timestamp = len as u64 * 400_000;
+            assert_eq!(
+                lch.new_target(timestamp).unwrap(),
+                Header::new_target_from_lch(lch.headers(), timestamp).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_save_new_headers() {
+        let path = temp_path();
+        let mut lch = chain(3);
+        lch.save_new_headers(&path).unwrap();
+        let full_lch = chain(5);
+        lch.add(full_lch.headers()[3].clone());
+        lch.add(full_lch.headers()[4].clone());
+        lch.save_new_headers(&path).unwrap();
+        assert_eq!(ids(&HeaderChain::load(&path, None).unwrap()), ids(&lch));
+
+        // a reorg replaces the last two headers
+        lch.truncate(3);
+        for timestamp in [1_799_999, 2_399_999] {
+            let header = lch.get_next_header([9; 32], timestamp).unwrap();
+            lch.add(header);
+        }
+        lch.save_new_headers(&path).unwrap();
+        let loaded = HeaderChain::load(&path, None).unwrap();
+        assert_eq!(ids(&loaded), ids(&lch));
+        assert_eq!(
+            fs::metadata(&path).unwrap().len(),
+            5 * HeaderChain::RECORD_SIZE as u64
+        );
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
@@ -0,0 +1,219 @@
+use crate::error::EbxError;
+use crate::header::Header;
+use crate::header_chain::HeaderChain;
+use crate::numbers::u256;
+use crate::pkh::Pkh;
+use crate::tx::Tx;
+use num_bigint::BigUint;
+use std::collections::VecDeque;
+
+// the end of a header chain, with only the headers that consensus needs: the
+// last LENGTH_SAFETY_PERIOD headers, which covers both expiry and the target
+// adjustment period. the sum of the targets of the target adjustment period is
+// kept as headers are added, so the target of the next block is found without
+// going over the period again. older headers are dropped.
+#[derive(Debug, Clone)]
+pub struct HeaderWindow {
+    headers: VecDeque<Header>,
+    first_block_num: u32,
+    target_sum: BigUint,
+}
+
+impl Default for HeaderWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HeaderWindow {
+    pub const MAX_LEN: u32 =
+        if HeaderChain::LENGTH_SAFETY_PERIOD > Header::BLOCKS_PER_TARGET_ADJ_PERIOD {
+            HeaderChain::LENGTH_SAFETY_PERIOD
+        } else {
+            Header::BLOCKS_PER_TARGET_ADJ_PERIOD
+        };
+
+    pub fn new() -> Self {
+        Self {
+            headers: VecDeque::new(),
+            first_block_num: 0,
+            target_sum: BigUint::default(),
+        }
+    }
+
+    // the window of headers, which must follow each other. headers may be a
+    // whole chain or only its end, but the end of a chain must have at least
+    // the headers of the target adjustment period, or the targets would be
+    // averaged over too few headers.
+    pub fn from_headers(headers: &[Header]) -> Result<Self, EbxError> {
+        let mut window = Self::new();
+        let first_block_num = headers.first().map_or(0, |header| header.block_num);
+        if first_block_num != 0 && headers.len() < Header::BLOCKS_PER_TARGET_ADJ_PERIOD as usize {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "too few headers for the target adjustment period".to_string(),
+            });
+        }
+        let start = headers.len().saturating_sub(Self::MAX_LEN as usize);
+        if let Some(first) = headers.get(start) {
+            window.first_block_num = first.block_num;
+        }
+        for header in &headers[start..] {
+            window.add(header.clone());
+        }
+        Ok(window)
+    }
+
+    pub fn add(&mut self, header: Header) -> &mut Self {
+        self.target_sum += header.target_num();
+        self.headers.push_back(header);
+        let adj_period = Header::BLOCKS_PER_TARGET_ADJ_PERIOD as usize;
+        if self.headers.len() > adj_period {
+            // the header that just left the target adjustment period
+            let old = &self.headers[self.headers.len() - 1 - adj_period];
+            self.target_sum -= old.target_num();
+        }
+        if self.headers.len() > Self::MAX_LEN as usize {
+            self.headers.pop_front();
+            self.first_block_num += 1;
+        }
+        self
+    }
+
+    // the length of the whole chain, which is the number of the next block
+    pub fn len(&self) -> u32 {
+        self.first_block_num + self.headers.len() as u32
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the number of the oldest header kept
+    pub fn first_block_num(&self) -> u32 {
+        self.first_block_num
+    }
+
+    pub fn get(&self, block_num: u32) -> Option<&Header> {
+        let index = block_num.checked_sub(self.first_block_num)?;
+        self.headers.get(index as usize)
+    }
+
+    pub fn get_tip(&self) -> Option<&Header> {
+        self.headers.back()
+    }
+
+    // the same as Header::new_target_from_lch on the whole chain
+    pub fn new_target(&self, new_timestamp: u64) -> Result<u256, EbxError> {
+        let len = self
+            .headers
+            .len()
+            .min(Header::BLOCKS_PER_TARGET_ADJ_PERIOD as usize);
+        Header::new_target_from_sum(
+            self.headers.get(self.headers.len() - len),
+            self.target_sum.clone(),
+            len as u32,
+            new_timestamp,
+        )
+    }
+
+    // the same as Header::is_valid_in_lch on the whole chain
+    pub fn new_header_is_valid(&self, header: &Header) -> bool {
+        header.is_valid_after(self.get_tip(), self.len(), |timestamp| {
+            self.new_target(timestamp)
+        })
+    }
+
+    pub fn new_header_is_valid_at(&self, header: &Header, timestamp: u64) -> bool {
+        header.is_timestamp_valid_at(timestamp) && self.new_header_is_valid(header)
+    }
+
+    pub fn new_header_is_valid_now(&self, header: &Header) -> bool {
+        self.new_header_is_valid_at(header, Header::get_new_timestamp())
+    }
+
+    pub fn get_next_coinbase_tx(&self, pkh: &Pkh, domain: &String) -> Tx {
+        HeaderChain::coinbase_tx(self.len(), pkh, domain)
+    }
+
+    pub fn get_next_header(
+        &self,
+        merkle_root: [u8; 32],
+        new_timestamp: u64,
+    ) -> Result<Header, EbxError> {
+        // valid block header, except for PoW
+        let mut header = match self.get_tip() {
+            Some(tip) => Header::from_prev(tip, self.new_target(new_timestamp)?, new_timestamp),
+            None => Header::from_genesis(new_timestamp),
+        };
+        header.merkle_root = merkle_root;
+        Ok(header)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_target_matches_header_chain() {
+        let mut lch = HeaderChain::new();
+        let mut window = HeaderWindow::new();
+        // blocks a bit faster than intended, so the target goes down
+        for block_num in 0..2100u32 {
+            let timestamp = block_num as u64 * 500_000 + (block_num % 7) as u64 * 1000;
+            let header = window.get_next_header([0; 32], timestamp).unwrap();
+            if block_num % 300 == 0 || (2014..=2018).contains(&block_num) {
+                let expected = lch.get_next_header([0; 32], timestamp).unwrap();
+                assert_eq!(header.to_buf(), expected.to_buf());
+            }
+            lch.add(header.clone());
+            window.add(header);
+        }
+        let tip = lch.get_tip().unwrap();
+        assert!(tip.target < lch.headers()[0].target);
+        assert_eq!(window.len(), 2100);
+        assert_eq!(window.get(5).unwrap().to_buf(), lch.headers()[5].to_buf());
+        assert!(window.get(2100).is_none());
+
+        // the window of the end of the chain gives the same target
+        let timestamp = 2100 * 500_000;
+        assert!(HeaderWindow::from_headers(&lch.headers()[100..]).is_err());
+        let from_end = HeaderWindow::from_headers(&lch.headers()[80..]).unwrap();
+        assert_eq!(from_end.len(), 2100);
+        assert_eq!(from_end.first_block_num(), 80);
+        assert_eq!(
+            from_end.new_target(timestamp).unwrap(),
+            Header::new_target_from_lch(lch.headers(), timestamp).unwrap()
+        );
+        // the first header of the target adjustment period is block 84
+        let first_timestamp = window.get(84).unwrap().timestamp;
+        assert!(window.new_target(first_timestamp).is_err());
+        assert!(window.new_target(first_timestamp + 1).is_ok());
+    }
+
+    #[test]
+    fn test_old_headers_are_dropped() {
+        let mut window = HeaderWindow::new();
+        let n = HeaderWindow::MAX_LEN + 10;
+        for block_num in 0..n {
+            let header = window
+                .get_next_header([0; 32], block_num as u64 * 600_000)
+                .unwrap();
+            window.add(header);
+        }
+        assert_eq!(window.len(), n);
+        assert_eq!(window.first_block_num(), 10);
+        assert!(window.get(9).is_none());
+        assert_eq!(window.get(10).unwrap().block_num, 10);
+        assert_eq!(window.get_tip().unwrap().block_num, n - 1);
+
+        let timestamp = n as u64 * 600_000;
+        let mut header = window.get_next_header([0; 32], timestamp).unwrap();
+        assert_eq!(header.block_num, n);
+        assert!(window.new_header_is_valid_at(&header, timestamp));
+        assert!(!window.new_header_is_valid_at(&header, timestamp - 1));
+        header.block_num = n + 1;
+        assert!(!window.new_header_is_valid(&header));
+    }
+}
@@ -1,17 +1,25 @@
 pub mod block;
 pub mod block_builder;
+pub mod block_filter;
+pub mod block_filter_chain;
+pub mod block_store;
+pub mod block_template;
 pub mod block_verifier;
 pub mod buf;
 pub mod buf_reader;
 pub mod buf_writer;
 pub mod domain;
 pub mod error;
+pub mod expired_tx_builder;
 pub mod hash;
 pub mod header;
 pub mod header_chain;
+pub mod header_window;
 pub mod key_pair;
-pub mod merkle_node;
+pub mod mempool;
+pub mod merkle_multi_proof;
 pub mod merkle_proof;
+pub mod merkle_tree;
 pub mod merkle_txs;
 pub mod numbers;
 pub mod opcode;
@@ -23,6 +31,11 @@ pub mod script;
 pub mod script_chunk;
 pub mod script_interpreter;
 pub mod script_num;
+pub mod script_template;
+pub mod sealed_tx;
+pub mod sig_cache;
+pub mod signer;
+pub mod spv_proof;
 pub mod tx;
 pub mod tx_builder;
 pub mod tx_in;
@@ -32,4 +45,6 @@ pub mod tx_out_bn_map;
 pub mod tx_signature;
 pub mod tx_signer;
 pub mod tx_verifier;
+pub mod utxo_commitment;
+pub mod utxo_snapshot;
 pub mod var_int;
@@ -0,0 +1,539 @@
+use crate::block::Block;
+use crate::error::EbxError;
+use crate::sealed_tx::SealedTx;
+use crate::sig_cache::SigCache;
+use crate::tx::Tx;
+use crate::tx_out_bn_map::TxOutBnMap;
+use crate::tx_verifier::TxVerifier;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+// the mempool holds txs that are valid for the next block but not yet
+// confirmed. txs are verified against the confirmed outputs plus the outputs
+// of txs already in the mempool, so a tx may spend the output of another
+// unconfirmed tx (a chain of txs). unconfirmed outputs are treated as if they
+// will be confirmed in the next block, i.e. working_block_num.
+//
+// txs that will be valid in a later block, because their lock_abs is in the
+// future or an input's lock_rel has not matured, are held in a separate
+// queue and moved into the mempool when the tip reaches them. queued txs do
+// not reserve the outputs they spend: if another tx spends the same output
+// first, the queued tx is dropped when it would have been released.
+//
+// txs are sealed when they are added, so their ids and sighash hashes are
+// computed once however many times they are verified.
+pub struct Mempool {
+    // confirmed outputs plus outputs created by mempool txs
+    tx_out_bn_map: TxOutBnMap,
+    working_block_num: u32,
+    txs: HashMap<[u8; 32], SealedTx>,
+    // tx ids in the order they were added. a tx is always added after the
+    // txs it spends from, so this order is also valid for a block.
+    tx_ids: Vec<[u8; 32]>,
+    // outpoint (tx_id, tx_out_num) -> id of the mempool tx that spends it
+    spent_by: HashMap<([u8; 32], u32), [u8; 32]>,
+    // txs that are not final at working_block_num, in the order they were
+    // added
+    queued_txs: Vec<SealedTx>,
+    sig_cache: Option<Arc<SigCache>>,
+}
+
+impl Mempool {
+    // tx_out_bn_map is the set of confirmed outputs as of the block before
+    // working_block_num
+    pub fn new(tx_out_bn_map: &TxOutBnMap, working_block_num: u32) -> Self {
+        Self {
+            tx_out_bn_map: tx_out_bn_map.clone(),
+            working_block_num,
+            txs: HashMap::new(),
+            tx_ids: Vec::new(),
+            spent_by: HashMap::new(),
+            queued_txs: Vec::new(),
+            sig_cache: None,
+        }
+    }
+
+    // signatures verified by the mempool are added to sig_cache. share it
+    // with BlockVerifier so that a block of txs already in the mempool does
+    // not verify their signatures again.
+    pub fn set_sig_cache(&mut self, sig_cache: Arc<SigCache>) {
+        self.sig_cache = Some(sig_cache);
+    }
+
+    pub fn working_block_num(&self) -> u32 {
+        self.working_block_num
+    }
+
+    pub fn len(&self) -> usize {
+        self.tx_ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tx_ids.is_empty()
+    }
+
+    pub fn contains(&self, tx_id: &[u8; 32]) -> bool {
+        self.txs.contains_key(tx_id)
+    }
+
+    pub fn get(&self, tx_id: &[u8; 32]) -> Option<&Tx> {
+        self.txs.get(tx_id).map(|sealed_tx| sealed_tx.tx())
+    }
+
+    // txs in the order they were added, parents before children
+    pub fn txs(&self) -> Vec<&Tx> {
+        self.tx_ids
+            .iter()
+            .map(|tx_id| self.txs[tx_id].tx())
+            .collect()
+    }
+
+    pub fn sealed_txs(&self) -> Vec<&SealedTx> {
+        self.tx_ids.iter().map(|tx_id| &self.txs[tx_id]).collect()
+    }
+
+    pub fn is_queued(&self, tx_id: &[u8; 32]) -> bool {
+        self.queued_txs.iter().any(|tx| &tx.id() == tx_id)
+    }
+
+    // txs waiting for lock_abs or lock_rel, in the order they were added
+    pub fn queued_txs(&self) -> Vec<&Tx> {
+        self.queued_txs
+            .iter()
+            .map(|sealed_tx| sealed_tx.tx())
+            .collect()
+    }
+
+    // the first block number at which tx is final, i.e. both its lock_abs
+    // and the lock_rel of every input are satisfied. returns None if an
+    // input spends an unknown output.
+    pub fn final_block_num(&self, tx: &Tx) -> Option<u32> {
+        let mut block_num = tx.lock_abs;
+        for input in &tx.inputs {
+            let tx_out_bn = self
+                .tx_out_bn_map
+                .get(&input.input_tx_id, input.input_tx_out_num)?;
+            block_num = block_num.max(tx_out_bn.block_num.saturating_add(input.lock_rel));
+        }
+        Some(block_num)
+    }
+
+    // ids of the mempool txs that already spend an outpoint spent by tx
+    pub fn conflicts(&self, tx: &Tx) -> Vec<[u8; 32]> {
+        let mut conflicts = Vec::new();
+        for input in &tx.inputs {
+            let outpoint = (input.input_tx_id, input.input_tx_out_num);
+            if let Some(tx_id) = self.spent_by.get(&outpoint) {
+                if !conflicts.contains(tx_id) {
+                    conflicts.push(*tx_id);
+                }
+            }
+        }
+        conflicts
+    }
+
+    // the outputs that can be spent by a new tx: confirmed and unconfirmed
+    // outputs not already spent by a mempool tx. useful for building a tx
+    // that depends on unconfirmed txs.
+    pub fn unspent_tx_out_bn_map(&self) -> TxOutBnMap {
+        let mut tx_out_bn_map = self.tx_out_bn_map.clone();
+        for (tx_id, tx_out_num) in self.spent_by.keys() {
+            tx_out_bn_map.remove(tx_id, *tx_out_num);
+        }
+        tx_out_bn_map
+    }
+
+    // adds tx to the mempool, or to the queue if it is valid but not final
+    // until a later block
+    pub fn add(&mut self, tx: Tx) -> Result<(), EbxError> {
+        self.add_sealed(SealedTx::new(tx))
+    }
+
+    pub fn add_sealed(&mut self, sealed_tx: SealedTx) -> Result<(), EbxError> {
+        let tx_id = sealed_tx.id();
+        let tx = sealed_tx.tx();
+        if self.txs.contains_key(&tx_id) || self.is_queued(&tx_id) {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "tx already in mempool".to_string(),
+            });
+        }
+        if !self.conflicts(tx).is_empty() {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "tx conflicts with mempool tx".to_string(),
+            });
+        }
+        // the script and values do not depend on the block number, so a tx
+        // that is valid at its final block number is only waiting for time
+        let block_num = match self.final_block_num(tx) {
+            Some(final_block_num) => final_block_num.max(self.working_block_num),
+            None => self.working_block_num,
+        };
+        let mut tx_verifier =
+            TxVerifier::from_sealed_tx(&sealed_tx, &self.tx_out_bn_map, block_num);
+        if let Some(sig_cache) = &self.sig_cache {
+            tx_verifier.set_sig_cache(sig_cache);
+        }
+        if !tx_verifier.verify() {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "tx failed verification".to_string(),
+            });
+        }
+        if block_num > self.working_block_num {
+            self.queued_txs.push(sealed_tx);
+            return Ok(());
+        }
+
+        for input in &tx.inputs {
+            self.spent_by
+                .insert((input.input_tx_id, input.input_tx_out_num), tx_id);
+        }
+        self.tx_out_bn_map
+            .add_sealed_tx_outputs(&sealed_tx, self.working_block_num);
+        self.tx_ids.push(tx_id);
+        self.txs.insert(tx_id, sealed_tx);
+        Ok(())
+    }
+
+    // updates the mempool for a new block. txs confirmed by the block are
+    // removed. the remaining txs are verified again against the new
+    // confirmed outputs and the new working block number, and any that are
+    // no longer valid (they conflict with a tx in the block, spend an output
+    // of such a tx, or are otherwise invalid now) are removed and returned.
+    // queued txs that are now final are moved into the mempool. the block
+    // must be the one at the working block number.
+    pub fn add_block(&mut self, block: &Block) -> Result<Vec<Tx>, EbxError> {
+        if block.header.block_num != self.working_block_num {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "block is not at working block number".to_string(),
+            });
+        }
+        let mut pending: Vec<SealedTx> = self
+            .tx_ids
+            .drain(..)
+            .map(|tx_id| self.txs.remove(&tx_id).unwrap())
+            .collect();
+        let queued_txs: Vec<SealedTx> = self.queued_txs.drain(..).collect();
+        self.spent_by.clear();
+        for sealed_tx in &pending {
+            for tx_out_num in 0..sealed_tx.tx().outputs.len() {
+                self.tx_out_bn_map
+                    .remove(&sealed_tx.id(), tx_out_num as u32);
+            }
+        }
+
+        let block_num = block.header.block_num;
+        let mut confirmed = HashSet::new();
+        for tx in &block.txs {
+            confirmed.insert(tx.id());
+            if !tx.is_coinbase() {
+                for input in &tx.inputs {
+                    self.tx_out_bn_map
+                        .remove(&input.input_tx_id, input.input_tx_out_num);
+                }
+            }
+            self.tx_out_bn_map.add_tx_outputs(tx, block_num);
+        }
+        self.working_block_num = block_num + 1;
+
+        // queued txs come after the mempool txs, so that a tx already in the
+        // mempool wins over a queued tx that spends the same output
+        pending.extend(queued_txs);
+        let mut evicted = Vec::new();
+        for sealed_tx in pending {
+            if confirmed.contains(&sealed_tx.id()) {
+                continue;
+            }
+            if self.add_sealed(sealed_tx.clone()).is_err() {
+                evicted.push(sealed_tx.into_tx());
+            }
+        }
+        Ok(evicted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::Header;
+    use crate::key_pair::KeyPair;
+    use crate::pkh::Pkh;
+    use crate::pkh_key_map::PkhKeyMap;
+    use crate::script::Script;
+    use crate::tx_builder::TxBuilder;
+    use crate::tx_in::TxIn;
+    use crate::tx_out::TxOut;
+    use crate::tx_signer::TxSigner;
+
+    struct Wallet {
+        pkh_key_map: PkhKeyMap,
+        script: Script,
+    }
+
+    impl Wallet {
+        fn new() -> Self {
+            let key = KeyPair::from_random();
+            let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec());
+            let mut pkh_key_map = PkhKeyMap::new();
+            pkh_key_map.add(key, &pkh.buf);
+            Self {
+                pkh_key_map,
+                script: Script::from_pkh_output(&pkh.buf),
+            }
+        }
+
+        // pays value to script from the wallet's outputs in tx_out_bn_map,
+        // with change back to the wallet
+        fn pay(&self, tx_out_bn_map: &TxOutBnMap, value: u64, script: &Script) -> Tx {
+            self.pay_with_lock_abs(tx_out_bn_map, value, script, 0)
+        }
+
+        fn pay_with_lock_abs(
+            &self,
+            tx_out_bn_map: &TxOutBnMap,
+            value: u64,
+            script: &Script,
+            lock_abs: u32,
+        ) -> Tx {
+            let mut own_tx_out_bn_map = TxOutBnMap::new();
+            for (name, tx_out_bn) in tx_out_bn_map.iter() {
+                if tx_out_bn.tx_out.script == self.script {
+                    let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(name).try_into().unwrap();
+                    let tx_out_num = TxOutBnMap::name_to_tx_out_num(name);
+                    own_tx_out_bn_map.add(
+                        &tx_id,
+                        tx_out_num,
+                        tx_out_bn.tx_out.clone(),
+                        tx_out_bn.block_num,
+                    );
+                }
+            }
+            let tx_out_bn_map = &own_tx_out_bn_map;
+            let mut tx_builder = TxBuilder::new(tx_out_bn_map, self.script.clone(), lock_abs);
+            tx_builder.add_output(TxOut::new(value, script.clone()));
+            let tx = tx_builder.build().unwrap();
+            let mut tx_signer = TxSigner::new(tx, tx_out_bn_map, &self.pkh_key_map, 1);
+            tx_signer.sign().unwrap()
+        }
+    }
+
+    fn block(block_num: u32, txs: Vec<Tx>) -> Block {
+        let mut header = Header::from_genesis(0);
+        header.block_num = block_num;
+        Block::new(header, txs)
+    }
+
+    #[test]
+    fn test_add_chained_txs() {
+        let wallet = Wallet::new();
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        let mut mempool = Mempool::new(&tx_out_bn_map, 1);
+
+        let other = Wallet::new();
+        let parent = wallet.pay(&mempool.unspent_tx_out_bn_map(), 60, &other.script);
+        mempool.add(parent.clone()).unwrap();
+
+        // the child spends the change of the parent, which is unconfirmed
+        let child = wallet.pay(&mempool.unspent_tx_out_bn_map(), 30, &other.script);
+        assert_eq!(child.inputs[0].input_tx_id, parent.id());
+        mempool.add(child.clone()).unwrap();
+
+        assert_eq!(mempool.len(), 2);
+        let tx_ids: Vec<_> = mempool.txs().iter().map(|tx| tx.id()).collect();
+        assert_eq!(tx_ids, vec![parent.id(), child.id()]);
+        assert!(mempool.add(child).is_err());
+    }
+
+    #[test]
+    fn test_reject_conflicting_and_invalid_txs() {
+        let wallet = Wallet::new();
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        let mut mempool = Mempool::new(&tx_out_bn_map, 1);
+
+        let tx_1 = wallet.pay(&tx_out_bn_map, 60, &Wallet::new().script);
+        let tx_2 = wallet.pay(&tx_out_bn_map, 50, &Wallet::new().script);
+        mempool.add(tx_1.clone()).unwrap();
+        assert_eq!(mempool.conflicts(&tx_2), vec![tx_1.id()]);
+        assert!(mempool.add(tx_2).is_err());
+
+        // spends an output that does not exist
+        let mut unknown_map = TxOutBnMap::new();
+        unknown_map.add(&[2; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        let tx_3 = wallet.pay(&unknown_map, 50, &Wallet::new().script);
+        assert!(mempool.add(tx_3).is_err());
+        assert_eq!(mempool.len(), 1);
+    }
+
+    #[test]
+    fn test_add_block_removes_confirmed_and_conflicting_txs() {
+        let wallet = Wallet::new();
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        tx_out_bn_map.add(&[2; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        let mut mempool = Mempool::new(&tx_out_bn_map, 1);
+
+        let mut utxos_1 = TxOutBnMap::new();
+        utxos_1.add(&[1; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        let mut utxos_2 = TxOutBnMap::new();
+        utxos_2.add(&[2; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+
+        let confirmed_tx = wallet.pay(&utxos_1, 10, &Wallet::new().script);
+        let parent = wallet.pay(&utxos_2, 20, &Wallet::new().script);
+        mempool.add(confirmed_tx.clone()).unwrap();
+        mempool.add(parent.clone()).unwrap();
+        let mut parent_outputs = TxOutBnMap::new();
+        parent_outputs.add_tx_outputs(&parent, 1);
+        let child = wallet.pay(&parent_outputs, 30, &Wallet::new().script);
+        mempool.add(child.clone()).unwrap();
+
+        // the block confirms one tx and a conflicting spend of the parent's
+        // input, which invalidates the parent and its child
+        let conflicting_tx = wallet.pay(&utxos_2, 40, &Wallet::new().script);
+        let coinbase = Tx::from_coinbase(Script::from_empty(), Script::from_empty(), 0, 1);
+        let evicted = mempool
+            .add_block(&block(1, vec![coinbase, confirmed_tx, conflicting_tx]))
+            .unwrap();
+
+        let evicted_ids: Vec<_> = evicted.iter().map(|tx| tx.id()).collect();
+        assert_eq!(evicted_ids, vec![parent.id(), child.id()]);
+        assert!(mempool.is_empty());
+        assert_eq!(mempool.working_block_num(), 2);
+    }
+
+    #[test]
+    fn test_add_block_keeps_unconfirmed_chain() {
+        let wallet = Wallet::new();
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        let mut mempool = Mempool::new(&tx_out_bn_map, 1);
+
+        let parent = wallet.pay(&mempool.unspent_tx_out_bn_map(), 60, &Wallet::new().script);
+        mempool.add(parent.clone()).unwrap();
+        let child = wallet.pay(&mempool.unspent_tx_out_bn_map(), 30, &Wallet::new().script);
+        mempool.add(child.clone()).unwrap();
+
+        // confirming the parent leaves the child spending a confirmed output
+        let coinbase = Tx::from_coinbase(Script::from_empty(), Script::from_empty(), 0, 1);
+        let evicted = mempool
+            .add_block(&block(1, vec![coinbase, parent]))
+            .unwrap();
+        assert!(evicted.is_empty());
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains(&child.id()));
+    }
+
+    #[test]
+    fn test_queue_tx_until_lock_abs() {
+        let wallet = Wallet::new();
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        let mut mempool = Mempool::new(&tx_out_bn_map, 1);
+
+        let tx = wallet.pay_with_lock_abs(&tx_out_bn_map, 60, &Wallet::new().script, 3);
+        assert_eq!(mempool.final_block_num(&tx), Some(3));
+        mempool.add(tx.clone()).unwrap();
+        assert!(mempool.is_empty());
+        assert!(mempool.is_queued(&tx.id()));
+        assert!(mempool.add(tx.clone()).is_err());
+
+        let coinbase = Tx::from_coinbase(Script::from_empty(), Script::from_empty(), 0, 1);
+        assert!(mempool
+            .add_block(&block(1, vec![coinbase]))
+            .unwrap()
+            .is_empty());
+        assert!(mempool.is_queued(&tx.id()));
+
+        let coinbase = Tx::from_coinbase(Script::from_empty(), Script::from_empty(), 0, 2);
+        assert!(mempool
+            .add_block(&block(2, vec![coinbase]))
+            .unwrap()
+            .is_empty());
+        assert!(mempool.contains(&tx.id()));
+        assert!(mempool.queued_txs().is_empty());
+    }
+
+    #[test]
+    fn test_queue_tx_until_lock_rel_matures() {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let script = Script::from_pkhx_1h_output(&[1; 32]);
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, script), 0);
+        let mut mempool = Mempool::new(&tx_out_bn_map, 1);
+
+        // spends the output through its expiry branch, which needs lock_rel
+        let input = TxIn::new(
+            [1; 32],
+            0,
+            Script::from_expired_pkhx_input(),
+            Script::PKHX_1H_LOCK_REL,
+        );
+        let output = TxOut::new(100, Script::from_pkh_output(&[2; 32]));
+        let tx = Tx::new(0, vec![input], vec![output], 0);
+        mempool.add(tx.clone()).unwrap();
+        assert!(mempool.is_queued(&tx.id()));
+
+        for block_num in 1..Script::PKHX_1H_LOCK_REL {
+            assert!(mempool.is_queued(&tx.id()));
+            let coinbase =
+                Tx::from_coinbase(Script::from_empty(), Script::from_empty(), 0, block_num);
+            assert!(mempool
+                .add_block(&block(block_num, vec![coinbase]))
+                .unwrap()
+                .is_empty());
+        }
+        assert!(mempool.contains(&tx.id()));
+
+        // a tx that is invalid even when final is rejected, not queued
+        let input = TxIn::new([1; 32], 0, Script::from_expired_pkhx_input(), 1);
+        let output = TxOut::new(100, Script::from_pkh_output(&[2; 32]));
+        let invalid_tx = Tx::new(0, vec![input], vec![output], 0);
+        let mut mempool = Mempool::new(&tx_out_bn_map, 1);
+        assert!(mempool.add(invalid_tx).is_err());
+        assert!(mempool.queued_txs().is_empty());
+    }
+
+    #[test]
+    fn test_drop_queued_tx_that_loses_its_input() {
+        let wallet = Wallet::new();
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        let mut mempool = Mempool::new(&tx_out_bn_map, 1);
+
+        let queued_tx = wallet.pay_with_lock_abs(&tx_out_bn_map, 60, &Wallet::new().script, 2);
+        mempool.add(queued_tx.clone()).unwrap();
+        // queued txs do not reserve their inputs
+        let tx = wallet.pay(&tx_out_bn_map, 50, &Wallet::new().script);
+        mempool.add(tx.clone()).unwrap();
+
+        let coinbase = Tx::from_coinbase(Script::from_empty(), Script::from_empty(), 0, 1);
+        let evicted = mempool.add_block(&block(1, vec![coinbase])).unwrap();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(evicted[0].id(), queued_tx.id());
+        assert!(mempool.contains(&tx.id()));
+    }
+
+    #[test]
+    fn test_add_block_out_of_order() {
+        let wallet = Wallet::new();
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, wallet.script.clone()), 0);
+        let mut mempool = Mempool::new(&tx_out_bn_map, 1);
+        let tx = wallet.pay(&tx_out_bn_map, 60, &Wallet::new().script);
+        mempool.add(tx.clone()).unwrap();
+
+        // a block that skips ahead, or one already added, changes nothing
+        for block_num in [0, 2] {
+            let coinbase =
+                Tx::from_coinbase(Script::from_empty(), Script::from_empty(), 0, block_num);
+            assert!(mempool
+                .add_block(&block(block_num, vec![coinbase]))
+                .is_err());
+        }
+        assert_eq!(mempool.working_block_num(), 1);
+        assert!(mempool.contains(&tx.id()));
+    }
+}
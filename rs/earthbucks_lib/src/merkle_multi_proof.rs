@@ -0,0 +1,191 @@
+use crate::buf::EbxBuf;
+use crate::buf_reader::BufReader;
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+use crate::merkle_tree::MerkleTree;
+
+// a proof that several leaves are in a merkle tree. hashes holds only the
+// siblings that can't be computed from the leaves, level by level from the
+// leaves up, and left to right within a level. see MerkleTree::multi_proof.
+#[derive(Debug, Clone)]
+pub struct MerkleMultiProof {
+    pub root: [u8; 32],
+    pub n_leaves: u32,
+    pub indices: Vec<u32>,
+    pub hashes: Vec<[u8; 32]>,
+}
+
+impl MerkleMultiProof {
+    pub fn new(root: [u8; 32], n_leaves: u32, indices: Vec<u32>, hashes: Vec<[u8; 32]>) -> Self {
+        Self {
+            root,
+            n_leaves,
+            indices,
+            hashes,
+        }
+    }
+
+    // leaves are the hashed datas at indices, in the same order. every hash
+    // of the proof must be used, and the result must be root.
+    pub fn verify(&self, leaves: &[[u8; 32]], root: &[u8; 32]) -> bool {
+        if self.root != *root || leaves.len() != self.indices.len() || leaves.is_empty() {
+            return false;
+        }
+        // n_leaves comes from untrusted input
+        let mut width = match Self::width(self.n_leaves) {
+            Some(width) => width,
+            None => return false,
+        };
+        if !self.indices.windows(2).all(|pair| pair[0] < pair[1]) {
+            return false;
+        }
+        if *self.indices.last().unwrap() >= self.n_leaves {
+            return false;
+        }
+        let mut nodes: Vec<(u32, [u8; 32])> = self
+            .indices
+            .iter()
+            .copied()
+            .zip(leaves.iter().copied())
+            .collect();
+        let mut hashes = self.hashes.iter();
+        while width > 1 {
+            let mut next_nodes = Vec::with_capacity(nodes.len());
+            let mut i = 0;
+            while i < nodes.len() {
+                let (index, node) = nodes[i];
+                let sibling = match nodes.get(i + 1) {
+                    Some((next_index, next_node))
+                        if index.is_multiple_of(2) && *next_index == index + 1 =>
+                    {
+                        i += 1;
+                        *next_node
+                    }
+                    _ => match hashes.next() {
+                        Some(hash) => *hash,
+                        None => return false,
+                    },
+                };
+                let parent = if index.is_multiple_of(2) {
+                    MerkleTree::hash_pair(&node, &sibling)
+                } else {
+                    MerkleTree::hash_pair(&sibling, &node)
+                };
+                next_nodes.push((index / 2, parent));
+                i += 1;
+            }
+            nodes = next_nodes;
+            width /= 2;
+        }
+        hashes.next().is_none() && nodes[0].1 == *root
+    }
+
+    // the width of the tree padded to a power of two, or None if n_leaves is
+    // 0 or too large
+    fn width(n_leaves: u32) -> Option<u32> {
+        if n_leaves == 0 {
+            return None;
+        }
+        n_leaves.checked_next_power_of_two()
+    }
+
+    pub fn to_buf(&self) -> Vec<u8> {
+        let mut bw = BufWriter::new();
+        bw.write(self.root.to_vec());
+        bw.write_u32_be(self.n_leaves);
+        bw.write_var_int(self.indices.len() as u64);
+        for index in &self.indices {
+            bw.write_u32_be(*index);
+        }
+        bw.write_var_int(self.hashes.len() as u64);
+        for hash in &self.hashes {
+            bw.write(hash.to_vec());
+        }
+        bw.to_buf()
+    }
+
+    pub fn from_buf_reader(br: &mut BufReader) -> Result<Self, EbxError> {
+        let root: [u8; 32] = br.read(32)?.try_into().unwrap();
+        let n_leaves = br.read_u32_be()?;
+        let width = Self::width(n_leaves).ok_or(EbxError::InvalidEncodingError { source: None })?;
+        // at most one index per leaf, and one hash per index per level
+        let n_indices = br.read_var_int()?;
+        if n_indices > n_leaves as u64 {
+            return Err(EbxError::InvalidEncodingError { source: None });
+        }
+        let mut indices = Vec::new();
+        for _ in 0..n_indices {
+            indices.push(br.read_u32_be()?);
+        }
+        let n_hashes = br.read_var_int()?;
+        if n_hashes > n_indices * width.trailing_zeros() as u64 {
+            return Err(EbxError::InvalidEncodingError { source: None });
+        }
+        let mut hashes = Vec::new();
+        for _ in 0..n_hashes {
+            hashes.push(br.read(32)?.try_into().unwrap());
+        }
+        Ok(Self::new(root, n_leaves, indices, hashes))
+    }
+
+    pub fn from_buf(buf: Vec<u8>) -> Result<Self, EbxError> {
+        let mut br = BufReader::new(buf);
+        let multi_proof = Self::from_buf_reader(&mut br)?;
+        if !br.eof() {
+            return Err(EbxError::TooMuchDataError { source: None });
+        }
+        Ok(multi_proof)
+    }
+
+    pub fn to_strict_str(&self) -> String {
+        self.to_buf().to_strict_hex()
+    }
+
+    pub fn from_strict_str(hex: &str) -> Result<Self, EbxError> {
+        let buf = Vec::<u8>::from_strict_hex(hex)
+            .map_err(|_| EbxError::InvalidHexError { source: None })?;
+        Self::from_buf(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::double_blake3_hash;
+
+    #[test]
+    fn to_strict_str_and_from_strict_str() {
+        let datas: Vec<[u8; 32]> = (0..6u32)
+            .map(|i| double_blake3_hash(&i.to_be_bytes()))
+            .collect();
+        let tree = MerkleTree::new(datas.clone());
+        let multi_proof = tree.multi_proof(&[1, 4]).unwrap();
+        let decoded = MerkleMultiProof::from_strict_str(&multi_proof.to_strict_str()).unwrap();
+        assert_eq!(decoded.to_buf(), multi_proof.to_buf());
+        assert!(decoded.verify(&[datas[1], datas[4]], &tree.root()));
+
+        // a proof with a hash left over is rejected
+        let mut extra = multi_proof.clone();
+        extra.hashes.push([0; 32]);
+        assert!(!extra.verify(&[datas[1], datas[4]], &tree.root()));
+
+        let mut buf = multi_proof.to_buf();
+        buf.push(0);
+        assert!(MerkleMultiProof::from_buf(buf).is_err());
+
+        // n_leaves of 0, or too large to pad, is rejected without panicking
+        for n_leaves in [0, u32::MAX] {
+            let mut bad = multi_proof.clone();
+            bad.n_leaves = n_leaves;
+            assert!(!bad.verify(&[datas[1], datas[4]], &tree.root()));
+            assert!(MerkleMultiProof::from_buf(bad.to_buf()).is_err());
+        }
+        // as are more indices or hashes than the tree can have
+        let mut bad = multi_proof.clone();
+        bad.n_leaves = 1;
+        assert!(MerkleMultiProof::from_buf(bad.to_buf()).is_err());
+        let mut bad = multi_proof.clone();
+        bad.hashes.extend(vec![[0; 32]; 10]);
+        assert!(MerkleMultiProof::from_buf(bad.to_buf()).is_err());
+    }
+}
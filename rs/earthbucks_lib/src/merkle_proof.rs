@@ -1,7 +1,7 @@
 use crate::buf::EbxBuf;
 use crate::buf_reader::BufReader;
 use crate::buf_writer::BufWriter;
-use crate::hash::double_blake3_hash;
+use crate::merkle_tree::MerkleTree;
 
 #[derive(Debug, Clone)]
 pub struct MerkleProof {
@@ -14,24 +14,22 @@ impl MerkleProof {
         Self { root, proof }
     }
 
+    // the proof lists the siblings from the top of the tree down, and is_left
+    // is true when the node being proven is on the left of its sibling
     pub fn verify(&self, hashed_data: &[u8; 32]) -> bool {
-        let mut hash = hashed_data.to_vec();
-        for (sibling, is_left) in &self.proof {
+        let mut hash = *hashed_data;
+        for (sibling, is_left) in self.proof.iter().rev() {
             hash = if *is_left {
-                let mut new_hash = sibling.to_vec();
-                new_hash.extend_from_slice(&hash);
-                double_blake3_hash(&new_hash).to_vec()
+                MerkleTree::hash_pair(&hash, sibling)
             } else {
-                let mut new_hash = hash.clone();
-                new_hash.extend_from_slice(sibling);
-                double_blake3_hash(&new_hash).to_vec()
+                MerkleTree::hash_pair(sibling, &hash)
             }
         }
         hash == self.root
     }
 
     pub fn verify_proof(data: &[u8; 32], proof: &MerkleProof, root: &[u8; 32]) -> bool {
-        proof.root == *root || proof.verify(data)
+        proof.root == *root && proof.verify(data)
     }
 
     pub fn position_in_tree(&self) -> u64 {
@@ -45,66 +43,36 @@ impl MerkleProof {
         position
     }
 
+    // the root, and a proof for every position of the tree padded to a power
+    // of two, including the padding
     pub fn generate_proofs_and_root(hashed_datas: Vec<[u8; 32]>) -> ([u8; 32], Vec<MerkleProof>) {
-        if hashed_datas.is_empty() {
-            panic!("Cannot create Merkle tree from empty array");
-        }
-        if hashed_datas.len() == 1 {
-            let root = hashed_datas[0];
-            let proof = MerkleProof::new(root, vec![]);
-            return (root, vec![proof]);
-        }
-        if hashed_datas.len() == 2 {
-            let mut combined = Vec::new();
-            combined.extend_from_slice(&hashed_datas[0]);
-            combined.extend_from_slice(&hashed_datas[1]);
-            let root = double_blake3_hash(&combined);
-            let proofs = vec![
-                MerkleProof::new(root, vec![(hashed_datas[1], true)]),
-                MerkleProof::new(root, vec![(hashed_datas[0], false)]),
-            ];
-            return (root, proofs);
-        }
-        let mut hashed_datas = hashed_datas;
-        while hashed_datas.len() & (hashed_datas.len() - 1) != 0 {
-            hashed_datas.push(*hashed_datas.last().unwrap());
+        let tree = MerkleTree::new(hashed_datas);
+        let width = 1 << tree.depth();
+        let proofs = (0..width).map(|index| tree.proof_at(index)).collect();
+        (tree.root(), proofs)
+    }
+
+    // the same root as generate_proofs_and_root, without building any proofs
+    pub fn root_from_hashed_datas(hashed_datas: Vec<[u8; 32]>, num_threads: usize) -> [u8; 32] {
+        MerkleTree::new_with_threads(hashed_datas, num_threads).root()
+    }
+
+    // the root, and whether the tree is mutated. see MerkleTree::is_mutated.
+    pub fn root_and_mutation_from_hashed_datas(
+        hashed_datas: Vec<[u8; 32]>,
+        num_threads: usize,
+    ) -> ([u8; 32], bool) {
+        let tree = MerkleTree::new_with_threads(hashed_datas, num_threads);
+        (tree.root(), tree.is_mutated())
+    }
+
+    // the proof for one leaf, the same as generate_proofs_and_root would
+    // return at index. returns None if index is out of range.
+    pub fn generate_proof(hashed_datas: Vec<[u8; 32]>, index: usize) -> Option<MerkleProof> {
+        if index >= hashed_datas.len() {
+            return None;
         }
-        let (left_root, left_proofs) =
-            Self::generate_proofs_and_root(hashed_datas[..hashed_datas.len() / 2].to_vec());
-        let (right_root, right_proofs) =
-            Self::generate_proofs_and_root(hashed_datas[hashed_datas.len() / 2..].to_vec());
-        let mut combined = Vec::new();
-        combined.extend_from_slice(&left_root);
-        combined.extend_from_slice(&right_root);
-        let root = double_blake3_hash(&combined);
-        let proofs = [
-            left_proofs
-                .into_iter()
-                .map(|proof| {
-                    MerkleProof::new(
-                        root,
-                        [(right_root, true)]
-                            .into_iter()
-                            .chain(proof.proof)
-                            .collect(),
-                    )
-                })
-                .collect::<Vec<_>>(),
-            right_proofs
-                .into_iter()
-                .map(|proof| {
-                    MerkleProof::new(
-                        root,
-                        [(left_root, false)]
-                            .into_iter()
-                            .chain(proof.proof)
-                            .collect(),
-                    )
-                })
-                .collect::<Vec<_>>(),
-        ]
-        .concat();
-        (root, proofs)
+        MerkleTree::new(hashed_datas).proof(index)
     }
 
     pub fn to_buf(&self) -> Vec<u8> {
@@ -144,6 +112,7 @@ impl MerkleProof {
 mod tests {
     use super::*;
     use crate::buf::EbxBuf;
+    use crate::hash::double_blake3_hash;
 
     #[test]
     fn generate_proofs_and_root_with_1_data() {
@@ -506,4 +475,73 @@ mod tests {
         let hex2 = hex::encode(new_proof.root);
         assert_eq!(hex1, hex2);
     }
+
+    #[test]
+    fn root_and_proof_by_index_match_generate_proofs_and_root() {
+        for n in 1..=33 {
+            let datas: Vec<[u8; 32]> = (0..n)
+                .map(|i| double_blake3_hash(format!("data{}", i).as_bytes()))
+                .collect();
+            let (root, proofs) = MerkleProof::generate_proofs_and_root(datas.clone());
+            assert_eq!(MerkleProof::root_from_hashed_datas(datas.clone(), 1), root);
+            // proofs also has the padding leaves, past n
+            for (i, proof) in proofs.iter().take(n).enumerate() {
+                let proof_by_index = MerkleProof::generate_proof(datas.clone(), i).unwrap();
+                assert_eq!(proof_by_index.root, root);
+                assert_eq!(proof_by_index.proof, proof.proof);
+            }
+            assert!(MerkleProof::generate_proof(datas, n).is_none());
+        }
+    }
+
+    #[test]
+    fn root_from_hashed_datas_with_threads() {
+        // large enough for the levels to be split across threads
+        let datas: Vec<[u8; 32]> = (0..5000u32)
+            .map(|i| double_blake3_hash(&i.to_be_bytes()))
+            .collect();
+        let (root, _) = MerkleProof::generate_proofs_and_root(datas.clone());
+        for num_threads in [1, 2, 3, 8] {
+            assert_eq!(
+                MerkleProof::root_from_hashed_datas(datas.clone(), num_threads),
+                root
+            );
+        }
+    }
+
+    #[test]
+    fn mutation_from_repeated_leaves() {
+        let datas: Vec<[u8; 32]> = (0..5u32)
+            .map(|i| double_blake3_hash(&i.to_be_bytes()))
+            .collect();
+        let (root, mutated) = MerkleProof::root_and_mutation_from_hashed_datas(datas.clone(), 1);
+        assert!(!mutated);
+
+        // repeating the last leaf gives the same root, but is detected
+        let mut mutated_datas = datas.clone();
+        mutated_datas.push(datas[4]);
+        let (mutated_root, mutated) =
+            MerkleProof::root_and_mutation_from_hashed_datas(mutated_datas, 1);
+        assert_eq!(mutated_root, root);
+        assert!(mutated);
+
+        // identical siblings above the leaves
+        let mut datas = datas[..4].to_vec();
+        datas.extend_from_slice(&datas.clone());
+        let (_, mutated) = MerkleProof::root_and_mutation_from_hashed_datas(datas, 1);
+        assert!(mutated);
+    }
+
+    #[test]
+    fn mutation_with_threads() {
+        let mut datas: Vec<[u8; 32]> = (0..5000u32)
+            .map(|i| double_blake3_hash(&i.to_be_bytes()))
+            .collect();
+        let (root, mutated) = MerkleProof::root_and_mutation_from_hashed_datas(datas.clone(), 4);
+        assert!(!mutated);
+        datas.push(*datas.last().unwrap());
+        let (mutated_root, mutated) = MerkleProof::root_and_mutation_from_hashed_datas(datas, 4);
+        assert_eq!(mutated_root, root);
+        assert!(mutated);
+    }
 }
@@ -0,0 +1,291 @@
+use crate::hash::double_blake3_hash;
+use crate::merkle_multi_proof::MerkleMultiProof;
+use crate::merkle_proof::MerkleProof;
+use std::collections::HashSet;
+
+// a merkle tree with every level kept, so proofs for any leaf or set of leaves
+// can be taken without hashing again. the leaves are padded to a power of two
+// by repeating the last leaf, the same as MerkleProof::generate_proofs_and_root.
+// the padding is not stored: every position past the end of a level has the
+// same value, which is kept in pads.
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    levels: Vec<Vec<[u8; 32]>>,
+    pads: Vec<[u8; 32]>,
+}
+
+impl MerkleTree {
+    // levels with fewer pairs than this per thread are hashed on one thread
+    const MIN_PAIRS_PER_THREAD: usize = 1024;
+
+    pub fn new(hashed_datas: Vec<[u8; 32]>) -> Self {
+        Self::new_with_threads(hashed_datas, 1)
+    }
+
+    // the same tree, with large levels hashed on up to num_threads threads
+    pub fn new_with_threads(hashed_datas: Vec<[u8; 32]>, num_threads: usize) -> Self {
+        if hashed_datas.is_empty() {
+            panic!("Cannot create Merkle tree from empty array");
+        }
+        let mut width = hashed_datas.len().next_power_of_two();
+        let mut pads = vec![*hashed_datas.last().unwrap()];
+        let mut levels = vec![hashed_datas];
+        while width > 1 {
+            let level = levels.last().unwrap();
+            let pad = *pads.last().unwrap();
+            let next_level = Self::hash_level(level, &pad, num_threads);
+            levels.push(next_level);
+            pads.push(Self::hash_pair(&pad, &pad));
+            width /= 2;
+        }
+        Self { levels, pads }
+    }
+
+    // the level above level, which is padded with pad
+    fn hash_level(level: &[[u8; 32]], pad: &[u8; 32], num_threads: usize) -> Vec<[u8; 32]> {
+        let hash_pairs = |pairs: &[[u8; 32]]| -> Vec<[u8; 32]> {
+            pairs
+                .chunks(2)
+                .map(|pair| Self::hash_pair(&pair[0], pair.get(1).unwrap_or(pad)))
+                .collect()
+        };
+        let n_pairs = level.len().div_ceil(2);
+        if num_threads <= 1 || n_pairs < Self::MIN_PAIRS_PER_THREAD * 2 {
+            return hash_pairs(level);
+        }
+        let chunk_pairs = n_pairs.div_ceil(num_threads);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = level
+                .chunks(chunk_pairs * 2)
+                .map(|chunk| scope.spawn(move || hash_pairs(chunk)))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    // padding repeats the last leaf, so appending copies of the last leaf to
+    // a list does not change its root. a list with a repeated leaf, or with
+    // identical siblings anywhere in the tree, may be such a copy of a
+    // shorter list. siblings of padding are not compared, as the last leaf
+    // always equals its padding.
+    pub fn is_mutated(&self) -> bool {
+        let mut seen = HashSet::with_capacity(self.len());
+        if !self.leaves().iter().all(|leaf| seen.insert(*leaf)) {
+            return true;
+        }
+        self.levels
+            .iter()
+            .any(|level| level.chunks_exact(2).any(|pair| pair[0] == pair[1]))
+    }
+
+    pub fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut combined = [0u8; 64];
+        combined[..32].copy_from_slice(left);
+        combined[32..].copy_from_slice(right);
+        double_blake3_hash(&combined)
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.levels.last().unwrap()[0]
+    }
+
+    // the number of leaves, not counting padding
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the number of levels below the root
+    pub fn depth(&self) -> usize {
+        self.levels.len() - 1
+    }
+
+    pub fn leaves(&self) -> &[[u8; 32]] {
+        &self.levels[0]
+    }
+
+    // the node at index of level, which may be padding
+    fn node(&self, level: usize, index: usize) -> [u8; 32] {
+        match self.levels[level].get(index) {
+            Some(node) => *node,
+            None => self.pads[level],
+        }
+    }
+
+    // the proof for a leaf or a padding position, with the siblings listed
+    // from the top of the tree down
+    pub(crate) fn proof_at(&self, index: usize) -> MerkleProof {
+        let mut index = index;
+        let mut proof = Vec::with_capacity(self.depth());
+        for level in 0..self.depth() {
+            proof.push((self.node(level, index ^ 1), index.is_multiple_of(2)));
+            index /= 2;
+        }
+        proof.reverse();
+        MerkleProof::new(self.root(), proof)
+    }
+
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.len() {
+            return None;
+        }
+        Some(self.proof_at(index))
+    }
+
+    pub fn proofs(&self) -> Vec<MerkleProof> {
+        (0..self.len()).map(|index| self.proof_at(index)).collect()
+    }
+
+    // one proof for several leaves. siblings shared between the leaves, and
+    // nodes that can be computed from the leaves, are left out. returns None
+    // if there are no indices or one is out of range.
+    pub fn multi_proof(&self, indices: &[usize]) -> Option<MerkleMultiProof> {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        if indices.is_empty() || *indices.last().unwrap() >= self.len() {
+            return None;
+        }
+        let leaf_indices: Vec<u32> = indices.iter().map(|index| *index as u32).collect();
+        let mut hashes = Vec::new();
+        for level in 0..self.depth() {
+            let mut next_indices = Vec::with_capacity(indices.len());
+            let mut i = 0;
+            while i < indices.len() {
+                let index = indices[i];
+                if index.is_multiple_of(2) && indices.get(i + 1) == Some(&(index + 1)) {
+                    // both siblings are known
+                    i += 2;
+                } else {
+                    hashes.push(self.node(level, index ^ 1));
+                    i += 1;
+                }
+                next_indices.push(index / 2);
+            }
+            indices = next_indices;
+        }
+        Some(MerkleMultiProof::new(
+            self.root(),
+            self.len() as u32,
+            leaf_indices,
+            hashes,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datas(n: u32) -> Vec<[u8; 32]> {
+        (0..n)
+            .map(|i| double_blake3_hash(&i.to_be_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn root_matches_known_roots() {
+        let datas: Vec<[u8; 32]> = (1..=4)
+            .map(|i| double_blake3_hash(format!("data{}", i).as_bytes()))
+            .collect();
+        assert_eq!(
+            hex::encode(MerkleTree::new(datas[..1].to_vec()).root()),
+            "689ce4d2c5a083571f0a1b1d8d4bb9a5b5494aba2c98eb606c1d265681ac5244"
+        );
+        assert_eq!(
+            hex::encode(MerkleTree::new(datas[..2].to_vec()).root()),
+            "fdc77b5c255818023a45501e5a5ce7f2e0ea275546cad26df121d4b8f17d8cde"
+        );
+        assert_eq!(
+            hex::encode(MerkleTree::new(datas[..3].to_vec()).root()),
+            "30a6a79ea9df78385494a1df6a6eeb4fcf318929899fd0b6c96bba0724bcecdf"
+        );
+        assert_eq!(
+            hex::encode(MerkleTree::new(datas).root()),
+            "a3344f480b6c8102dd11ad1b686aa2b890b8455bd5343f66b33d392b05b4f187"
+        );
+    }
+
+    #[test]
+    fn proofs_verify_strictly() {
+        for n in 1..=17 {
+            let datas = datas(n);
+            let tree = MerkleTree::new(datas.clone());
+            assert_eq!(
+                tree.root(),
+                MerkleProof::root_from_hashed_datas(datas.clone(), 1)
+            );
+            for (index, data) in datas.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(MerkleProof::verify_proof(data, &proof, &tree.root()));
+                assert_eq!(proof.position_in_tree(), index as u64);
+                // the wrong leaf or the wrong root fails
+                let other = double_blake3_hash(b"other");
+                assert!(!MerkleProof::verify_proof(&other, &proof, &tree.root()));
+                assert!(!MerkleProof::verify_proof(data, &proof, &other));
+            }
+            assert!(tree.proof(n as usize).is_none());
+        }
+    }
+
+    #[test]
+    fn new_with_threads() {
+        // large enough for the levels to be split across threads
+        let datas = datas(5000);
+        let tree = MerkleTree::new(datas.clone());
+        for num_threads in [2, 3, 8] {
+            let threaded = MerkleTree::new_with_threads(datas.clone(), num_threads);
+            assert_eq!(threaded.root(), tree.root());
+            assert_eq!(
+                threaded.proof(4999).unwrap().proof,
+                tree.proof(4999).unwrap().proof
+            );
+        }
+    }
+
+    #[test]
+    fn multi_proofs() {
+        for n in 1..=17u32 {
+            let datas = datas(n);
+            let tree = MerkleTree::new(datas.clone());
+            let n = n as usize;
+            let index_sets: Vec<Vec<usize>> = vec![
+                vec![0],
+                vec![n - 1],
+                (0..n).collect(),
+                (0..n).step_by(3).collect(),
+                vec![n - 1, 0, n / 2],
+            ];
+            for indices in index_sets {
+                let multi_proof = tree.multi_proof(&indices).unwrap();
+                let leaves: Vec<[u8; 32]> = multi_proof
+                    .indices
+                    .iter()
+                    .map(|index| datas[*index as usize])
+                    .collect();
+                assert!(multi_proof.verify(&leaves, &tree.root()));
+
+                let mut wrong_leaves = leaves.clone();
+                wrong_leaves[0] = double_blake3_hash(b"other");
+                assert!(!multi_proof.verify(&wrong_leaves, &tree.root()));
+                assert!(!multi_proof.verify(&leaves, &[0; 32]));
+            }
+            assert!(tree.multi_proof(&[]).is_none());
+            assert!(tree.multi_proof(&[n]).is_none());
+        }
+
+        // all the leaves need no hashes
+        let tree = MerkleTree::new(datas(8));
+        let multi_proof = tree.multi_proof(&(0..8).collect::<Vec<_>>()).unwrap();
+        assert!(multi_proof.hashes.is_empty());
+        // two neighbours share their proof
+        let multi_proof = tree.multi_proof(&[2, 3]).unwrap();
+        assert_eq!(multi_proof.hashes.len(), 2);
+    }
+}
@@ -1,4 +1,6 @@
 use crate::merkle_proof::MerkleProof;
+use crate::merkle_tree::MerkleTree;
+use crate::sealed_tx::SealedTx;
 use crate::tx::Tx;
 
 pub struct MerkleTxs {
@@ -10,8 +12,52 @@ pub struct MerkleTxs {
 impl MerkleTxs {
     pub fn new(txs: Vec<Tx>) -> Self {
         let hashed_datas: Vec<[u8; 32]> = txs.iter().map(|tx| tx.id()).collect::<Vec<_>>();
-        let (root, proofs) = MerkleProof::generate_proofs_and_root(hashed_datas);
-        Self { txs, root, proofs }
+        let tree = MerkleTree::new(hashed_datas);
+        Self {
+            txs,
+            root: tree.root(),
+            proofs: tree.proofs(),
+        }
+    }
+
+    // uses the ids already computed by the sealed txs
+    pub fn from_sealed_txs(sealed_txs: Vec<SealedTx>) -> Self {
+        let hashed_datas: Vec<[u8; 32]> = sealed_txs.iter().map(|tx| tx.id()).collect();
+        let tree = MerkleTree::new(hashed_datas);
+        let txs = sealed_txs.into_iter().map(|tx| tx.into_tx()).collect();
+        Self {
+            txs,
+            root: tree.root(),
+            proofs: tree.proofs(),
+        }
+    }
+
+    // the ids of txs, hashed on num_threads threads
+    pub fn tx_ids(txs: &[Tx], num_threads: usize) -> Vec<[u8; 32]> {
+        if num_threads <= 1 || txs.len() < 2 {
+            return txs.iter().map(|tx| tx.id()).collect();
+        }
+        let chunk_size = txs.len().div_ceil(num_threads);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = txs
+                .chunks(chunk_size)
+                .map(|txs| scope.spawn(move || txs.iter().map(|tx| tx.id()).collect::<Vec<_>>()))
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().unwrap())
+                .collect()
+        })
+    }
+
+    // the merkle root of txs, without copying the txs or building proofs
+    pub fn root_from_txs(txs: &[Tx], num_threads: usize) -> [u8; 32] {
+        MerkleProof::root_from_hashed_datas(Self::tx_ids(txs, num_threads), num_threads)
+    }
+
+    // the proof for the tx at index alone, without building the others
+    pub fn proof_from_txs(txs: &[Tx], index: usize, num_threads: usize) -> Option<MerkleProof> {
+        MerkleProof::generate_proof(Self::tx_ids(txs, num_threads), index)
     }
 
     pub fn get_iterator(&self) -> impl Iterator<Item = (&Tx, &MerkleProof)> {
@@ -60,4 +106,27 @@ mod tests {
         let verified = merkle_txs.verify();
         assert!(verified);
     }
+
+    #[test]
+    fn from_sealed_txs_matches_new() {
+        let txs: Vec<Tx> = (0..3).map(|i| Tx::new(0, vec![], vec![], i)).collect();
+        let sealed_txs = txs.iter().cloned().map(SealedTx::new).collect();
+        let merkle_txs = MerkleTxs::from_sealed_txs(sealed_txs);
+        assert_eq!(merkle_txs.root, MerkleTxs::new(txs).root);
+        assert!(merkle_txs.verify());
+    }
+
+    #[test]
+    fn root_from_txs_matches_new() {
+        let txs: Vec<Tx> = (0..37).map(|i| Tx::new(0, vec![], vec![], i)).collect();
+        let root = MerkleTxs::new(txs.clone()).root;
+        for num_threads in [1, 4] {
+            assert_eq!(MerkleTxs::root_from_txs(&txs, num_threads), root);
+        }
+        let merkle_txs = MerkleTxs::new(txs.clone());
+        for index in [0, 17, 36] {
+            let proof = MerkleTxs::proof_from_txs(&txs, index, 2).unwrap();
+            assert_eq!(proof.proof, merkle_txs.proofs[index].proof);
+        }
+    }
 }
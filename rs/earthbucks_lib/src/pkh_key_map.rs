@@ -1,5 +1,9 @@
 use crate::buf::EbxBuf;
+use crate::error::EbxError;
 use crate::key_pair::KeyPair;
+use crate::pub_key::PubKey;
+use crate::signer::Signer;
+use secp256k1::{Message, Secp256k1, SecretKey};
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Default)]
@@ -34,6 +38,30 @@ impl PkhKeyMap {
     }
 }
 
+impl Signer for PkhKeyMap {
+    fn get_pub_key(&self, pkh: &[u8; 32]) -> Option<PubKey> {
+        self.get(pkh).map(|key_pair| key_pair.pub_key.clone())
+    }
+
+    fn sign(&self, pkh: &[u8; 32], sighash: &[u8; 32]) -> Result<[u8; 64], EbxError> {
+        let key_pair = match self.get(pkh) {
+            Some(key_pair) => key_pair,
+            None => {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "key not found".to_string(),
+                })
+            }
+        };
+        let secp = Secp256k1::new();
+        let message = Message::from_digest_slice(sighash).expect("32 bytes");
+        let key = SecretKey::from_slice(&key_pair.priv_key.buf)
+            .map_err(|_| EbxError::InvalidKeyError { source: None })?;
+        let sig = secp.sign_ecdsa(&message, &key);
+        Ok(sig.serialize_compact())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -109,4 +137,26 @@ mod tests {
         assert!(values_encoded.contains(&key1_encoded));
         assert!(values_encoded.contains(&key2_encoded));
     }
+
+    #[test]
+    fn test_signer() {
+        let mut pkh_key_map = PkhKeyMap::new();
+        let key = KeyPair::from_random();
+        let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec());
+        pkh_key_map.add(key.clone(), &pkh.buf);
+
+        let pub_key = pkh_key_map.get_pub_key(&pkh.buf).unwrap();
+        assert_eq!(pub_key.buf, key.pub_key.buf);
+
+        let sighash = [1u8; 32];
+        let sig_buf = pkh_key_map.sign(&pkh.buf, &sighash).unwrap();
+        let secp = Secp256k1::new();
+        let message = Message::from_digest_slice(&sighash).unwrap();
+        let sig = secp256k1::ecdsa::Signature::from_compact(&sig_buf).unwrap();
+        let public_key = secp256k1::PublicKey::from_slice(&key.pub_key.buf).unwrap();
+        assert!(secp.verify_ecdsa(&message, &sig, &public_key).is_ok());
+
+        assert!(pkh_key_map.get_pub_key(&[0; 32]).is_none());
+        assert!(pkh_key_map.sign(&[0; 32], &sighash).is_err());
+    }
 }
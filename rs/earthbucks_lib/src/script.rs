@@ -127,6 +127,16 @@ impl Script {
             && self.chunks[1].buffer.as_ref().unwrap().len() == PubKey::SIZE
     }
 
+    // sizes of the serialized input scripts. placeholders have the same size
+    // as signed scripts, so these are the sizes once signed.
+    // pushdata1 of a signature or pubkey costs 2 bytes plus the data.
+    pub const PKH_INPUT_SIZE: usize = 2 + TxSignature::SIZE + 2 + PubKey::SIZE;
+    // also the size of an unexpired pkhxr input, which has the same chunks
+    pub const UNEXPIRED_PKHX_INPUT_SIZE: usize = Script::PKH_INPUT_SIZE + 1;
+    pub const EXPIRED_PKHX_INPUT_SIZE: usize = 1;
+    pub const RECOVERY_PKHXR_INPUT_SIZE: usize = Script::PKH_INPUT_SIZE + 2;
+    pub const EXPIRED_PKHXR_INPUT_SIZE: usize = 2;
+
     pub fn from_pkh_input_placeholder() -> Self {
         let sig_buf = vec![0; TxSignature::SIZE];
         let pub_key = vec![0; PubKey::SIZE];
@@ -507,6 +517,24 @@ impl Script {
         )
     }
 
+    // the size of this input script once signed. known input templates
+    // (signed or placeholder) have a fixed size; anything else is measured.
+    pub fn signed_input_size(&self) -> usize {
+        if self.is_pkh_input() {
+            Script::PKH_INPUT_SIZE
+        } else if self.is_unexpired_pkhx_input() {
+            Script::UNEXPIRED_PKHX_INPUT_SIZE
+        } else if self.is_recovery_pkhxr_input() {
+            Script::RECOVERY_PKHXR_INPUT_SIZE
+        } else if self.is_expired_pkhx_input() {
+            Script::EXPIRED_PKHX_INPUT_SIZE
+        } else if self.is_expired_pkhxr_input() {
+            Script::EXPIRED_PKHXR_INPUT_SIZE
+        } else {
+            self.to_buf().len()
+        }
+    }
+
     pub fn is_push_only(&self) -> bool {
         for chunk in &self.chunks {
             if chunk.opcode > Opcode::OP_16 {
@@ -528,6 +556,31 @@ impl Script {
     pub fn is_standard_output(&self) -> bool {
         self.is_pkhx_90d_output() || self.is_pkhx_1h_output()
     }
+
+    // the pkhs an output pays to: the pkh of a pkh, pkhx or pkhxr output, and
+    // also the recovery pkh of a pkhxr output. empty for other scripts.
+    pub fn output_pkhs(&self) -> Vec<[u8; 32]> {
+        let n_chunks: &[usize] = if self.is_pkh_output() {
+            &[2]
+        } else if self.is_pkhx_1h_output() || self.is_pkhx_90d_output() {
+            &[3]
+        } else if self.is_pkhxr_1h_40m_output() || self.is_pkhxr_90d_60d_output() {
+            &[3, 13]
+        } else {
+            &[]
+        };
+        n_chunks
+            .iter()
+            .map(|n_chunk| {
+                self.chunks[*n_chunk]
+                    .buffer
+                    .clone()
+                    .unwrap()
+                    .try_into()
+                    .unwrap()
+            })
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -644,6 +697,54 @@ mod tests {
         assert!(!script.is_pkh_output());
     }
 
+    #[test]
+    fn test_output_pkhs() {
+        let (pkh, rpkh) = ([1; 32], [2; 32]);
+        assert_eq!(Script::from_pkh_output(&pkh).output_pkhs(), vec![pkh]);
+        assert_eq!(Script::from_pkhx_1h_output(&pkh).output_pkhs(), vec![pkh]);
+        assert_eq!(Script::from_pkhx_90d_output(&pkh).output_pkhs(), vec![pkh]);
+        assert_eq!(
+            Script::from_pkhxr_1h_40m_output(&pkh, &rpkh).output_pkhs(),
+            vec![pkh, rpkh]
+        );
+        assert_eq!(
+            Script::from_pkhxr_90d_60d_output(&pkh, &rpkh).output_pkhs(),
+            vec![pkh, rpkh]
+        );
+        assert!(Script::from_empty().output_pkhs().is_empty());
+    }
+
+    #[test]
+    fn test_input_sizes_match_placeholders() {
+        let inputs = vec![
+            (Script::from_pkh_input_placeholder(), Script::PKH_INPUT_SIZE),
+            (
+                Script::from_unexpired_pkhx_input_placeholder(),
+                Script::UNEXPIRED_PKHX_INPUT_SIZE,
+            ),
+            (
+                Script::from_unexpired_pkhxr_input_placeholder(),
+                Script::UNEXPIRED_PKHX_INPUT_SIZE,
+            ),
+            (
+                Script::from_recovery_pkhxr_input_placeholder(),
+                Script::RECOVERY_PKHXR_INPUT_SIZE,
+            ),
+            (
+                Script::from_expired_pkhx_input(),
+                Script::EXPIRED_PKHX_INPUT_SIZE,
+            ),
+            (
+                Script::from_expired_pkhxr_input(),
+                Script::EXPIRED_PKHXR_INPUT_SIZE,
+            ),
+        ];
+        for (script, size) in inputs {
+            assert_eq!(script.to_buf().len(), size);
+            assert_eq!(script.signed_input_size(), size);
+        }
+    }
+
     // standard test vectors
 
     #[derive(Deserialize)]
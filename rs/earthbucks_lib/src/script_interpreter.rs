@@ -3,14 +3,15 @@ use crate::opcode::{Opcode, OP};
 use crate::pub_key::PubKey;
 use crate::script::Script;
 use crate::script_num::ScriptNum;
+use crate::sig_cache::SigCache;
 use crate::tx::{HashCache, Tx};
 use crate::tx_signature::TxSignature;
 use num_bigint::{BigInt, ToBigInt};
 use num_traits::ToPrimitive;
 
 pub struct ScriptInterpreter<'a> {
-    pub script: Script,
-    pub tx: Tx,
+    pub script: &'a Script,
+    pub tx: &'a Tx,
     pub n_in: usize,
     pub stack: Vec<Vec<u8>>,
     pub alt_stack: Vec<Vec<u8>>,
@@ -22,12 +23,13 @@ pub struct ScriptInterpreter<'a> {
     pub err_str: String,
     pub value: u64,
     pub hash_cache: &'a mut HashCache,
+    pub sig_cache: Option<&'a SigCache>,
 }
 
 impl<'a> ScriptInterpreter<'a> {
     pub fn from_script_tx(
-        script: Script,
-        tx: Tx,
+        script: &'a Script,
+        tx: &'a Tx,
         n_in: usize,
         hash_cache: &'a mut HashCache,
     ) -> Self {
@@ -45,12 +47,13 @@ impl<'a> ScriptInterpreter<'a> {
             err_str: "".to_string(),
             value: 0,
             hash_cache,
+            sig_cache: None,
         }
     }
 
     pub fn from_output_script_tx(
-        script: Script,
-        tx: Tx,
+        script: &'a Script,
+        tx: &'a Tx,
         n_in: usize,
         stack: Vec<Vec<u8>>,
         value: u64,
@@ -70,9 +73,31 @@ impl<'a> ScriptInterpreter<'a> {
             err_str: "".to_string(),
             value,
             hash_cache,
+            sig_cache: None,
         }
     }
 
+    // signatures are looked up in, and added to, sig_cache
+    pub fn set_sig_cache(&mut self, sig_cache: &'a SigCache) {
+        self.sig_cache = Some(sig_cache);
+    }
+
+    fn verify_signature(
+        &mut self,
+        pub_key: [u8; PubKey::SIZE],
+        signature: TxSignature,
+        exec_script_buf: Vec<u8>,
+    ) -> bool {
+        let sighash = self.tx.sighash_with_cache(
+            self.n_in,
+            exec_script_buf,
+            self.value,
+            signature.hash_type,
+            self.hash_cache,
+        );
+        Tx::verify_sighash(&sighash, pub_key, &signature, self.sig_cache)
+    }
+
     pub fn cast_to_bool(buf: &[u8]) -> bool {
         !buf.iter().all(|&x| x == 0)
     }
@@ -949,14 +974,8 @@ impl<'a> ScriptInterpreter<'a> {
                             )
                         });
 
-                    let success = self.tx.verify_with_cache(
-                        self.n_in,
-                        pub_key_arr,
-                        signature.unwrap(),
-                        exec_script_buf,
-                        self.value,
-                        self.hash_cache,
-                    );
+                    let success =
+                        self.verify_signature(pub_key_arr, signature.unwrap(), exec_script_buf);
 
                     self.stack.push(if success { vec![1] } else { vec![] });
                     if opcode == OP["CHECKSIGVERIFY"] && !success {
@@ -1010,13 +1029,10 @@ impl<'a> ScriptInterpreter<'a> {
                     let mut matched_sigs = 0;
                     for sig in sigs {
                         for j in 0..pub_keys.len() {
-                            let success = self.tx.verify_with_cache(
-                                self.n_in,
+                            let success = self.verify_signature(
                                 pub_keys[j][..PubKey::SIZE].try_into().unwrap(),
                                 TxSignature::from_buf(sig.clone()).unwrap(),
                                 exec_script_buf.clone(),
-                                self.value,
-                                self.hash_cache,
                             );
                             if success {
                                 matched_sigs += 1;
@@ -1113,7 +1129,7 @@ mod tests {
             let script = Script::from_strict_str("0").unwrap();
             let mut hash_cache = HashCache::new();
             let mut script_interpreter =
-                ScriptInterpreter::from_script_tx(script, tx, 0, &mut hash_cache);
+                ScriptInterpreter::from_script_tx(&script, &tx, 0, &mut hash_cache);
             script_interpreter.eval_script();
             assert_eq!(script_interpreter.return_success, Some(false));
             assert_eq!(hex::encode(script_interpreter.return_value.unwrap()), "");
@@ -1125,7 +1141,7 @@ mod tests {
             let script = Script::from_strict_str("0xff").unwrap();
             let mut hash_cache = HashCache::new();
             let mut script_interpreter =
-                ScriptInterpreter::from_script_tx(script, tx, 0, &mut hash_cache);
+                ScriptInterpreter::from_script_tx(&script, &tx, 0, &mut hash_cache);
             script_interpreter.eval_script();
             assert_eq!(script_interpreter.return_success, Some(true));
             assert!(script_interpreter.return_value.is_some());
@@ -1139,7 +1155,7 @@ mod tests {
 
             let mut hash_cache = HashCache::new();
             let mut script_interpreter =
-                ScriptInterpreter::from_script_tx(script, tx, 0, &mut hash_cache);
+                ScriptInterpreter::from_script_tx(&script, &tx, 0, &mut hash_cache);
             script_interpreter.eval_script();
             assert_eq!(script_interpreter.return_success, Some(true));
             assert!(script_interpreter.return_value.is_some());
@@ -1155,7 +1171,7 @@ mod tests {
             let script = Script::from_strict_str(&("0x".to_owned() + &"ff".repeat(65536))).unwrap();
             let mut hash_cache = HashCache::new();
             let mut script_interpreter =
-                ScriptInterpreter::from_script_tx(script, tx, 0, &mut hash_cache);
+                ScriptInterpreter::from_script_tx(&script, &tx, 0, &mut hash_cache);
             script_interpreter.eval_script();
             assert_eq!(script_interpreter.return_success, Some(true));
             assert!(script_interpreter.return_value.is_some());
@@ -1171,7 +1187,7 @@ mod tests {
             let script = Script::from_strict_str("1NEGATE").unwrap();
             let mut hash_cache = HashCache::new();
             let mut script_interpreter =
-                ScriptInterpreter::from_script_tx(script, tx, 0, &mut hash_cache);
+                ScriptInterpreter::from_script_tx(&script, &tx, 0, &mut hash_cache);
             script_interpreter.eval_script();
             assert_eq!(script_interpreter.return_success, Some(true));
             assert!(script_interpreter.return_value.is_some());
@@ -1198,7 +1214,7 @@ mod tests {
             let output_tx_id = [0; 32];
             let output_tx_index = 0;
 
-            let mut tx = Tx::new(
+            let tx = Tx::new(
                 1,
                 vec![TxIn::new(
                     output_tx_id,
@@ -1222,8 +1238,8 @@ mod tests {
             let mut hash_cache = HashCache::new();
 
             let mut script_interpreter = ScriptInterpreter::from_output_script_tx(
-                output_script,
-                tx,
+                &output_script,
+                &tx,
                 0,
                 stack,
                 output_amount,
@@ -1273,7 +1289,7 @@ mod tests {
             let output_tx_index = 0;
 
             // Create a tx
-            let mut tx = Tx::new(
+            let tx = Tx::new(
                 1,
                 vec![TxIn::new(
                     output_tx_id,
@@ -1307,8 +1323,8 @@ mod tests {
 
             // Create a script interpreter
             let mut script_interpreter = ScriptInterpreter::from_output_script_tx(
-                output_script,
-                tx,
+                &output_script,
+                &tx,
                 0,
                 stack,
                 output_amount,
@@ -1363,7 +1379,7 @@ mod tests {
                 );
                 let mut hash_cache = HashCache::new();
                 let mut script_interpreter =
-                    ScriptInterpreter::from_script_tx(script, tx, 0, &mut hash_cache);
+                    ScriptInterpreter::from_script_tx(&script, &tx, 0, &mut hash_cache);
                 script_interpreter.eval_script();
                 assert_eq!(
                     script_interpreter.err_str, test_script.expected_error,
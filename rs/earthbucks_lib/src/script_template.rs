@@ -0,0 +1,402 @@
+use crate::error::EbxError;
+use crate::pub_key::PubKey;
+use crate::script::Script;
+use crate::signer::Signer;
+use crate::tx::Tx;
+use crate::tx_out::TxOut;
+use crate::tx_signature::TxSignature;
+use std::sync::Arc;
+
+// everything a template needs to know to spend one input: the tx being signed,
+// which input, the output it spends, the block that output was confirmed in,
+// and the block the tx is being built for.
+pub struct TemplateInput<'a> {
+    pub tx: &'a Tx,
+    pub n_in: usize,
+    pub tx_out: &'a TxOut,
+    pub prev_block_num: u32,
+    pub working_block_num: u32,
+}
+
+// a script template knows how to recognize one kind of output script and how
+// to spend it. TxBuilder uses templates to choose input placeholders and
+// TxSigner uses them to fill the placeholders in, so adding a new output type
+// means implementing this trait and registering it in ScriptTemplates.
+pub trait ScriptTemplate: Send + Sync {
+    fn is_match(&self, output_script: &Script) -> bool;
+
+    // the input script to put in the tx before signing. it must be the same
+    // size as the signed input script.
+    fn input_placeholder(&self, output_script: &Script) -> Script;
+
+    // returns the input script that spends input.tx_out. the current input
+    // script (usually a placeholder) selects the branch to sign.
+    fn sign_input(&self, input: &TemplateInput, signer: &dyn Signer) -> Result<Script, EbxError>;
+
+    // the input script and lock_rel that spend the output through its expiry
+    // branch, if it has one and it is expired at working_block_num
+    fn expired_input(
+        &self,
+        _output_script: &Script,
+        _prev_block_num: u32,
+        _working_block_num: u32,
+    ) -> Option<(Script, u32)> {
+        None
+    }
+
+    // the input placeholder and lock_rel that spend the output through its
+    // recovery branch, if it has one, it is locked to rpkh and the recovery
+    // window is open at working_block_num
+    fn recovery_input_placeholder(
+        &self,
+        _output_script: &Script,
+        _rpkh: &[u8; 32],
+        _prev_block_num: u32,
+        _working_block_num: u32,
+    ) -> Option<(Script, u32)> {
+        None
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ScriptTemplates {
+    templates: Vec<Arc<dyn ScriptTemplate>>,
+}
+
+impl ScriptTemplates {
+    pub fn new() -> Self {
+        Self {
+            templates: Vec::new(),
+        }
+    }
+
+    pub fn from_standard() -> Self {
+        let mut script_templates = Self::new();
+        script_templates.add(Arc::new(PkhTemplate));
+        script_templates.add(Arc::new(PkhxTemplate::from_pkhx_1h()));
+        script_templates.add(Arc::new(PkhxTemplate::from_pkhx_90d()));
+        script_templates.add(Arc::new(PkhxrTemplate::from_pkhxr_1h_40m()));
+        script_templates.add(Arc::new(PkhxrTemplate::from_pkhxr_90d_60d()));
+        script_templates
+    }
+
+    pub fn add(&mut self, template: Arc<dyn ScriptTemplate>) {
+        self.templates.push(template);
+    }
+
+    // templates are matched in the order they were added
+    pub fn find(&self, output_script: &Script) -> Option<&dyn ScriptTemplate> {
+        self.templates
+            .iter()
+            .find(|template| template.is_match(output_script))
+            .map(|template| template.as_ref())
+    }
+}
+
+fn sign_for_pkh(
+    input: &TemplateInput,
+    signer: &dyn Signer,
+    pkh_buf: &[u8; 32],
+) -> Result<([u8; TxSignature::SIZE], [u8; PubKey::SIZE]), EbxError> {
+    let pub_key = match signer.get_pub_key(pkh_buf) {
+        Some(pub_key) => pub_key,
+        None => {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "key not found".to_string(),
+            })
+        }
+    };
+    let sighash = input.tx.sighash_no_cache(
+        input.n_in,
+        input.tx_out.script.to_buf(),
+        input.tx_out.value,
+        TxSignature::SIGHASH_ALL,
+    );
+    let sig = TxSignature::new(TxSignature::SIGHASH_ALL, signer.sign(pkh_buf, &sighash)?);
+    Ok((sig.to_buf(), pub_key.buf))
+}
+
+fn pkh_at(output_script: &Script, n_chunk: usize) -> [u8; 32] {
+    output_script.chunks[n_chunk]
+        .buffer
+        .clone()
+        .expect("pkh not found")
+        .try_into()
+        .unwrap()
+}
+
+pub struct PkhTemplate;
+
+impl ScriptTemplate for PkhTemplate {
+    fn is_match(&self, output_script: &Script) -> bool {
+        output_script.is_pkh_output()
+    }
+
+    fn input_placeholder(&self, _output_script: &Script) -> Script {
+        Script::from_pkh_input_placeholder()
+    }
+
+    fn sign_input(&self, input: &TemplateInput, signer: &dyn Signer) -> Result<Script, EbxError> {
+        let input_script = &input.tx.inputs[input.n_in].script;
+        if !input_script.is_pkh_input() {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "expected pkh input placeholder".to_string(),
+            });
+        }
+        let pkh_buf = pkh_at(&input.tx_out.script, 2);
+        let (sig_buf, pub_key_buf) = sign_for_pkh(input, signer, &pkh_buf)?;
+        Ok(Script::from_pkh_input(&sig_buf, &pub_key_buf))
+    }
+}
+
+// pkh with expiry. the pkhx outputs differ only in their expiry period.
+pub struct PkhxTemplate {
+    is_output: fn(&Script) -> bool,
+    is_expired: fn(u32, u32) -> bool,
+    lock_rel: u32,
+}
+
+impl PkhxTemplate {
+    pub fn from_pkhx_1h() -> Self {
+        Self {
+            is_output: Script::is_pkhx_1h_output,
+            is_expired: Script::is_pkhx_1h_expired,
+            lock_rel: Script::PKHX_1H_LOCK_REL,
+        }
+    }
+
+    pub fn from_pkhx_90d() -> Self {
+        Self {
+            is_output: Script::is_pkhx_90d_output,
+            is_expired: Script::is_pkhx_90d_expired,
+            lock_rel: Script::PKHX_90D_LOCK_REL,
+        }
+    }
+}
+
+impl ScriptTemplate for PkhxTemplate {
+    fn is_match(&self, output_script: &Script) -> bool {
+        (self.is_output)(output_script)
+    }
+
+    fn input_placeholder(&self, _output_script: &Script) -> Script {
+        Script::from_unexpired_pkhx_input_placeholder()
+    }
+
+    fn sign_input(&self, input: &TemplateInput, signer: &dyn Signer) -> Result<Script, EbxError> {
+        let input_script = &input.tx.inputs[input.n_in].script;
+        if (self.is_expired)(input.working_block_num, input.prev_block_num) {
+            if input_script.is_expired_pkhx_input() {
+                // no need to sign expired pkhx
+                return Ok(input_script.clone());
+            } else {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "expected expired pkhx input".to_string(),
+                });
+            }
+        }
+        if !input_script.is_unexpired_pkhx_input() {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "expected unexpired pkhx input placeholder".to_string(),
+            });
+        }
+        let pkh_buf = pkh_at(&input.tx_out.script, 3);
+        let (sig_buf, pub_key_buf) = sign_for_pkh(input, signer, &pkh_buf)?;
+        Ok(Script::from_unexpired_pkhx_input(&sig_buf, &pub_key_buf))
+    }
+
+    fn expired_input(
+        &self,
+        _output_script: &Script,
+        prev_block_num: u32,
+        working_block_num: u32,
+    ) -> Option<(Script, u32)> {
+        if !(self.is_expired)(working_block_num, prev_block_num) {
+            return None;
+        }
+        Some((Script::from_expired_pkhx_input(), self.lock_rel))
+    }
+}
+
+// pkh with expiry and recovery. the pkhxr outputs differ only in their expiry
+// and recovery periods.
+pub struct PkhxrTemplate {
+    is_output: fn(&Script) -> bool,
+    is_expired: fn(u32, u32) -> bool,
+    is_recoverable: fn(u32, u32) -> bool,
+    x_lock_rel: u32,
+    r_lock_rel: u32,
+}
+
+impl PkhxrTemplate {
+    pub fn from_pkhxr_1h_40m() -> Self {
+        Self {
+            is_output: Script::is_pkhxr_1h_40m_output,
+            is_expired: Script::is_pkhxr_1h_40m_expired,
+            is_recoverable: Script::is_pkhxr_1h_40m_recoverable,
+            x_lock_rel: Script::PKHXR_1H_40M_X_LOCK_REL,
+            r_lock_rel: Script::PKHXR_1H_40M_R_LOCK_REL,
+        }
+    }
+
+    pub fn from_pkhxr_90d_60d() -> Self {
+        Self {
+            is_output: Script::is_pkhxr_90d_60d_output,
+            is_expired: Script::is_pkhxr_90d_60d_expired,
+            is_recoverable: Script::is_pkhxr_90d_60d_recoverable,
+            x_lock_rel: Script::PKHXR_90D_60D_X_LOCK_REL,
+            r_lock_rel: Script::PKHXR_90D_60D_R_LOCK_REL,
+        }
+    }
+}
+
+impl ScriptTemplate for PkhxrTemplate {
+    fn is_match(&self, output_script: &Script) -> bool {
+        (self.is_output)(output_script)
+    }
+
+    fn input_placeholder(&self, _output_script: &Script) -> Script {
+        Script::from_unexpired_pkhxr_input_placeholder()
+    }
+
+    fn sign_input(&self, input: &TemplateInput, signer: &dyn Signer) -> Result<Script, EbxError> {
+        let input_script = &input.tx.inputs[input.n_in].script;
+        if (self.is_expired)(input.working_block_num, input.prev_block_num) {
+            if input_script.is_expired_pkhxr_input() {
+                // no need to sign expired pkhxr
+                return Ok(input_script.clone());
+            } else {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "expected expired pkhx input".to_string(),
+                });
+            }
+        }
+
+        if input_script.is_recovery_pkhxr_input() {
+            if !(self.is_recoverable)(input.working_block_num, input.prev_block_num) {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "expected recoverable pkhx input".to_string(),
+                });
+            }
+            let rpkh_buf = pkh_at(&input.tx_out.script, 13);
+            let (sig_buf, pub_key_buf) = sign_for_pkh(input, signer, &rpkh_buf)?;
+            Ok(Script::from_recovery_pkhxr_input(&sig_buf, &pub_key_buf))
+        } else if input_script.is_unexpired_pkhxr_input() {
+            let pkh_buf = pkh_at(&input.tx_out.script, 3);
+            let (sig_buf, pub_key_buf) = sign_for_pkh(input, signer, &pkh_buf)?;
+            Ok(Script::from_unexpired_pkhxr_input(&sig_buf, &pub_key_buf))
+        } else {
+            Err(EbxError::GenericError {
+                source: None,
+                message: "expected unexpired pkhx input placeholder".to_string(),
+            })
+        }
+    }
+
+    fn expired_input(
+        &self,
+        _output_script: &Script,
+        prev_block_num: u32,
+        working_block_num: u32,
+    ) -> Option<(Script, u32)> {
+        if !(self.is_expired)(working_block_num, prev_block_num) {
+            return None;
+        }
+        Some((Script::from_expired_pkhxr_input(), self.x_lock_rel))
+    }
+
+    fn recovery_input_placeholder(
+        &self,
+        output_script: &Script,
+        rpkh: &[u8; 32],
+        prev_block_num: u32,
+        working_block_num: u32,
+    ) -> Option<(Script, u32)> {
+        if &pkh_at(output_script, 13) != rpkh
+            || !(self.is_recoverable)(working_block_num, prev_block_num)
+            || (self.is_expired)(working_block_num, prev_block_num)
+        {
+            return None;
+        }
+        Some((
+            Script::from_recovery_pkhxr_input_placeholder(),
+            self.r_lock_rel,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_standard_templates() {
+        let script_templates = ScriptTemplates::from_standard();
+        let pkh = [1; 32];
+        let outputs = vec![
+            (Script::from_pkh_output(&pkh), Script::PKH_INPUT_SIZE),
+            (
+                Script::from_pkhx_1h_output(&pkh),
+                Script::UNEXPIRED_PKHX_INPUT_SIZE,
+            ),
+            (
+                Script::from_pkhx_90d_output(&pkh),
+                Script::UNEXPIRED_PKHX_INPUT_SIZE,
+            ),
+            (
+                Script::from_pkhxr_1h_40m_output(&pkh, &pkh),
+                Script::UNEXPIRED_PKHX_INPUT_SIZE,
+            ),
+            (
+                Script::from_pkhxr_90d_60d_output(&pkh, &pkh),
+                Script::UNEXPIRED_PKHX_INPUT_SIZE,
+            ),
+        ];
+        for (output_script, input_size) in outputs {
+            let template = script_templates.find(&output_script).unwrap();
+            assert!(template.is_match(&output_script));
+            let placeholder = template.input_placeholder(&output_script);
+            assert_eq!(placeholder.to_buf().len(), input_size);
+        }
+        assert!(script_templates.find(&Script::from_empty()).is_none());
+        assert!(ScriptTemplates::new()
+            .find(&Script::from_pkh_output(&pkh))
+            .is_none());
+    }
+
+    #[test]
+    fn test_expired_and_recovery_inputs() {
+        let template = PkhxrTemplate::from_pkhxr_90d_60d();
+        let output_script = Script::from_pkhxr_90d_60d_output(&[1; 32], &[2; 32]);
+
+        assert!(template.expired_input(&output_script, 0, 0).is_none());
+        let (input_script, lock_rel) = template
+            .expired_input(&output_script, 0, Script::PKHXR_90D_60D_X_LOCK_REL)
+            .unwrap();
+        assert!(input_script.is_expired_pkhxr_input());
+        assert_eq!(lock_rel, Script::PKHXR_90D_60D_X_LOCK_REL);
+
+        let recovery_block_num = Script::PKHXR_90D_60D_R_LOCK_REL;
+        assert!(template
+            .recovery_input_placeholder(&output_script, &[1; 32], 0, recovery_block_num)
+            .is_none());
+        assert!(template
+            .recovery_input_placeholder(&output_script, &[2; 32], 0, recovery_block_num - 1)
+            .is_none());
+        let (input_script, lock_rel) = template
+            .recovery_input_placeholder(&output_script, &[2; 32], 0, recovery_block_num)
+            .unwrap();
+        assert!(input_script.is_recovery_pkhxr_input());
+        assert_eq!(lock_rel, Script::PKHXR_90D_60D_R_LOCK_REL);
+
+        assert!(PkhTemplate
+            .expired_input(&Script::from_pkh_output(&[1; 32]), 0, u32::MAX)
+            .is_none());
+    }
+}
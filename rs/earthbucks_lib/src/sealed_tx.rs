@@ -0,0 +1,107 @@
+use crate::tx::{HashCache, Tx};
+
+// a tx that can no longer change, with its id and the hashes used for
+// sighashes computed once. Tx recomputes these on every call, which
+// serializes and hashes the whole tx; code that holds on to a tx and needs
+// its id or verifies it repeatedly (the mempool, block verification, merkle
+// roots, utxo updates) should seal it first.
+#[derive(Clone, Debug)]
+pub struct SealedTx {
+    tx: Tx,
+    id: [u8; 32],
+    prevouts_hash: [u8; 32],
+    lock_rel_hash: [u8; 32],
+    outputs_hash: [u8; 32],
+}
+
+impl SealedTx {
+    pub fn new(tx: Tx) -> Self {
+        let id = tx.id();
+        let prevouts_hash = tx.hash_prevouts();
+        let lock_rel_hash = tx.hash_lock_rel();
+        let outputs_hash = tx.hash_outputs();
+        Self {
+            tx,
+            id,
+            prevouts_hash,
+            lock_rel_hash,
+            outputs_hash,
+        }
+    }
+
+    pub fn tx(&self) -> &Tx {
+        &self.tx
+    }
+
+    pub fn into_tx(self) -> Tx {
+        self.tx
+    }
+
+    pub fn id(&self) -> [u8; 32] {
+        self.id
+    }
+
+    pub fn hash_prevouts(&self) -> [u8; 32] {
+        self.prevouts_hash
+    }
+
+    pub fn hash_lock_rel(&self) -> [u8; 32] {
+        self.lock_rel_hash
+    }
+
+    pub fn hash_outputs(&self) -> [u8; 32] {
+        self.outputs_hash
+    }
+
+    // a hash cache that is already filled in, for sighash_with_cache and
+    // verify_with_cache
+    pub fn hash_cache(&self) -> HashCache {
+        HashCache {
+            prevouts_hash: Some(self.prevouts_hash),
+            lock_rel_hash: Some(self.lock_rel_hash),
+            outputs_hash: Some(self.outputs_hash),
+        }
+    }
+}
+
+impl From<Tx> for SealedTx {
+    fn from(tx: Tx) -> Self {
+        Self::new(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Script;
+    use crate::tx_in::TxIn;
+    use crate::tx_out::TxOut;
+    use crate::tx_signature::TxSignature;
+
+    #[test]
+    fn test_hashes_match_tx() {
+        let input = TxIn::new([1; 32], 2, Script::from_empty(), 3);
+        let output = TxOut::new(100, Script::from_pkh_output(&[4; 32]));
+        let tx = Tx::new(1, vec![input], vec![output], 5);
+        let sealed_tx = SealedTx::new(tx.clone());
+
+        assert_eq!(sealed_tx.id(), tx.id());
+        assert_eq!(sealed_tx.hash_prevouts(), tx.hash_prevouts());
+        assert_eq!(sealed_tx.hash_lock_rel(), tx.hash_lock_rel());
+        assert_eq!(sealed_tx.hash_outputs(), tx.hash_outputs());
+
+        let script = Script::from_pkh_output(&[4; 32]).to_buf();
+        let mut hash_cache = sealed_tx.hash_cache();
+        assert_eq!(
+            sealed_tx.tx().sighash_with_cache(
+                0,
+                script.clone(),
+                100,
+                TxSignature::SIGHASH_ALL,
+                &mut hash_cache
+            ),
+            tx.sighash_no_cache(0, script, 100, TxSignature::SIGHASH_ALL)
+        );
+        assert_eq!(sealed_tx.into_tx().to_buf(), tx.to_buf());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::hash::blake3_hash;
+use crate::pub_key::PubKey;
+use crate::tx_signature::TxSignature;
+use std::collections::{HashSet, VecDeque};
+use std::sync::RwLock;
+
+// a cache of signatures that have already been verified. a tx is verified
+// when it enters the mempool and again when the block containing it is
+// verified; with the cache shared between the two, the second time only
+// needs the sighash, not the ecdsa verification. only valid signatures are
+// stored, keyed by the hash of (sighash, public key, signature). once full,
+// the oldest entries are evicted first.
+pub struct SigCache {
+    max_entries: usize,
+    entries: RwLock<SigCacheEntries>,
+}
+
+#[derive(Default)]
+struct SigCacheEntries {
+    keys: HashSet<[u8; 32]>,
+    order: VecDeque<[u8; 32]>,
+}
+
+impl SigCache {
+    pub const DEFAULT_MAX_ENTRIES: usize = 100_000;
+
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            entries: RwLock::new(SigCacheEntries::default()),
+        }
+    }
+
+    fn key(
+        sighash: &[u8; 32],
+        pub_key: &[u8; PubKey::SIZE],
+        signature: &[u8; TxSignature::SIZE],
+    ) -> [u8; 32] {
+        let mut data = Vec::with_capacity(32 + PubKey::SIZE + TxSignature::SIZE);
+        data.extend_from_slice(sighash);
+        data.extend_from_slice(pub_key);
+        data.extend_from_slice(signature);
+        blake3_hash(&data)
+    }
+
+    pub fn contains(
+        &self,
+        sighash: &[u8; 32],
+        pub_key: &[u8; PubKey::SIZE],
+        signature: &[u8; TxSignature::SIZE],
+    ) -> bool {
+        let key = Self::key(sighash, pub_key, signature);
+        self.entries.read().unwrap().keys.contains(&key)
+    }
+
+    // records a signature that was verified to be valid
+    pub fn insert(
+        &self,
+        sighash: &[u8; 32],
+        pub_key: &[u8; PubKey::SIZE],
+        signature: &[u8; TxSignature::SIZE],
+    ) {
+        if self.max_entries == 0 {
+            return;
+        }
+        let key = Self::key(sighash, pub_key, signature);
+        let mut entries = self.entries.write().unwrap();
+        if !entries.keys.insert(key) {
+            return;
+        }
+        entries.order.push_back(key);
+        while entries.order.len() > self.max_entries {
+            let oldest = entries.order.pop_front().unwrap();
+            entries.keys.remove(&oldest);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for SigCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_ENTRIES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_evict() {
+        let sig_cache = SigCache::new(2);
+        let pub_key = [2; PubKey::SIZE];
+        let signature = [3; TxSignature::SIZE];
+        sig_cache.insert(&[1; 32], &pub_key, &signature);
+        sig_cache.insert(&[1; 32], &pub_key, &signature);
+        assert_eq!(sig_cache.len(), 1);
+        assert!(sig_cache.contains(&[1; 32], &pub_key, &signature));
+        assert!(!sig_cache.contains(&[1; 32], &pub_key, &[4; TxSignature::SIZE]));
+
+        sig_cache.insert(&[5; 32], &pub_key, &signature);
+        sig_cache.insert(&[6; 32], &pub_key, &signature);
+        assert_eq!(sig_cache.len(), 2);
+        assert!(!sig_cache.contains(&[1; 32], &pub_key, &signature));
+        assert!(sig_cache.contains(&[5; 32], &pub_key, &signature));
+        assert!(sig_cache.contains(&[6; 32], &pub_key, &signature));
+
+        let sig_cache = SigCache::new(0);
+        sig_cache.insert(&[1; 32], &pub_key, &signature);
+        assert!(sig_cache.is_empty());
+    }
+}
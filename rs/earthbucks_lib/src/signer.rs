@@ -0,0 +1,15 @@
+use crate::error::EbxError;
+use crate::pub_key::PubKey;
+
+// a signer holds (or has access to) the private keys for some set of pkhs.
+// TxSigner never sees private keys directly: it computes the sighash for an
+// input, asks the signer for the public key and a signature, and places those
+// into the input script. this lets signing be delegated to a separate process,
+// a hardware-style service, or a remote co-signer.
+pub trait Signer {
+    fn get_pub_key(&self, pkh: &[u8; 32]) -> Option<PubKey>;
+
+    // returns a compact (r, s) signature of the 32-byte sighash using the key
+    // that hashes to pkh
+    fn sign(&self, pkh: &[u8; 32], sighash: &[u8; 32]) -> Result<[u8; 64], EbxError>;
+}
@@ -0,0 +1,206 @@
+use crate::block::Block;
+use crate::buf::EbxBuf;
+use crate::buf_reader::BufReader;
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+use crate::header_chain::HeaderChain;
+use crate::merkle_proof::MerkleProof;
+use crate::tx::Tx;
+
+// proof that a tx is in a block, for light clients that keep only the header
+// chain: the tx id, the id of the block, and the merkle path from the tx up to
+// the merkle root in the block's header. the path lists the siblings from the
+// top of the tree down, the same as MerkleProof.
+#[derive(Debug, Clone)]
+pub struct SpvProof {
+    pub tx_id: [u8; 32],
+    pub block_id: [u8; 32],
+    pub merkle_path: Vec<([u8; 32], bool)>,
+}
+
+impl SpvProof {
+    // a block can't have more than u32::MAX txs
+    pub const MAX_MERKLE_PATH_LEN: usize = 32;
+
+    pub fn new(tx_id: [u8; 32], block_id: [u8; 32], merkle_path: Vec<([u8; 32], bool)>) -> Self {
+        Self {
+            tx_id,
+            block_id,
+            merkle_path,
+        }
+    }
+
+    // the proof for the tx with tx_id, or None if block doesn't contain it
+    pub fn from_block(block: &Block, tx_id: &[u8; 32]) -> Option<Self> {
+        let tx_ids: Vec<[u8; 32]> = block.txs.iter().map(|tx| tx.id()).collect();
+        let index = tx_ids.iter().position(|id| id == tx_id)?;
+        let merkle_proof = MerkleProof::generate_proof(tx_ids, index)?;
+        Some(Self::new(*tx_id, block.header.id(), merkle_proof.proof))
+    }
+
+    fn error(message: &str) -> EbxError {
+        EbxError::GenericError {
+            source: None,
+            message: message.to_string(),
+        }
+    }
+
+    // checks that the tx with tx_id is in a block of lch, that the header of
+    // the block is valid in lch, and that the block has at least
+    // min_confirmations. the block itself is the first confirmation. returns
+    // the block number.
+    pub fn verify(
+        &self,
+        tx_id: &[u8; 32],
+        lch: &HeaderChain,
+        min_confirmations: u32,
+    ) -> Result<u32, EbxError> {
+        if *tx_id != self.tx_id {
+            return Err(Self::error("tx id does not match proof"));
+        }
+        let block_num = lch
+            .find_block_num(&self.block_id)
+            .ok_or_else(|| Self::error("block is not in header chain"))?;
+        let header = &lch.headers()[block_num as usize];
+        if header.id() != self.block_id
+            || !header.is_valid_in_lch(&lch.headers()[..block_num as usize])
+        {
+            return Err(Self::error("block header is invalid"));
+        }
+        let confirmations = lch.headers().len() as u32 - block_num;
+        if confirmations < min_confirmations {
+            return Err(Self::error("not enough confirmations"));
+        }
+        let merkle_proof = MerkleProof::new(header.merkle_root, self.merkle_path.clone());
+        if !MerkleProof::verify_proof(tx_id, &merkle_proof, &header.merkle_root) {
+            return Err(Self::error("tx is not in block"));
+        }
+        Ok(block_num)
+    }
+
+    pub fn verify_tx(
+        &self,
+        tx: &Tx,
+        lch: &HeaderChain,
+        min_confirmations: u32,
+    ) -> Result<u32, EbxError> {
+        self.verify(&tx.id(), lch, min_confirmations)
+    }
+
+    pub fn to_buf(&self) -> Vec<u8> {
+        let mut bw = BufWriter::new();
+        bw.write(self.tx_id.to_vec());
+        bw.write(self.block_id.to_vec());
+        bw.write_var_int(self.merkle_path.len() as u64);
+        for (sibling, is_left) in &self.merkle_path {
+            bw.write(sibling.to_vec());
+            bw.write_u8(if *is_left { 1 } else { 0 });
+        }
+        bw.to_buf()
+    }
+
+    pub fn from_buf_reader(br: &mut BufReader) -> Result<Self, EbxError> {
+        let tx_id: [u8; 32] = br.read(32)?.try_into().unwrap();
+        let block_id: [u8; 32] = br.read(32)?.try_into().unwrap();
+        let path_len = br.read_var_int()? as usize;
+        if path_len > Self::MAX_MERKLE_PATH_LEN {
+            return Err(Self::error("merkle path is too long"));
+        }
+        let mut merkle_path = Vec::with_capacity(path_len);
+        for _ in 0..path_len {
+            let sibling: [u8; 32] = br.read(32)?.try_into().unwrap();
+            let is_left = match br.read_u8()? {
+                0 => false,
+                1 => true,
+                _ => return Err(EbxError::InvalidEncodingError { source: None }),
+            };
+            merkle_path.push((sibling, is_left));
+        }
+        Ok(Self::new(tx_id, block_id, merkle_path))
+    }
+
+    pub fn from_buf(buf: Vec<u8>) -> Result<Self, EbxError> {
+        let mut br = BufReader::new(buf);
+        let spv_proof = Self::from_buf_reader(&mut br)?;
+        if !br.eof() {
+            return Err(EbxError::TooMuchDataError { source: None });
+        }
+        Ok(spv_proof)
+    }
+
+    pub fn to_strict_str(&self) -> String {
+        self.to_buf().to_strict_hex()
+    }
+
+    pub fn from_strict_str(hex: &str) -> Result<Self, EbxError> {
+        let buf = Vec::<u8>::from_strict_hex(hex)
+            .map_err(|_| EbxError::InvalidHexError { source: None })?;
+        Self::from_buf(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_txs::MerkleTxs;
+    use crate::pkh::Pkh;
+
+    // a chain of n_blocks blocks, each with the coinbase and three other txs
+    fn setup(n_blocks: u32) -> (HeaderChain, Vec<Block>) {
+        let mut lch = HeaderChain::new();
+        let mut blocks = vec![];
+        let pkh = Pkh::from_pub_key_buffer(vec![2; 33]);
+        let domain = "example.com".to_string();
+        for block_num in 0..n_blocks {
+            let mut txs = vec![lch.get_next_coinbase_tx(&pkh, &domain)];
+            for i in 0..3 {
+                txs.push(Tx::new(1, vec![], vec![], block_num * 10 + i));
+            }
+            let merkle_root = MerkleTxs::new(txs.clone()).root;
+            let timestamp = block_num as u64 * 600_000;
+            let header = lch.get_next_header(merkle_root, timestamp).unwrap();
+            lch.add(header.clone());
+            blocks.push(Block::new(header, txs));
+        }
+        (lch, blocks)
+    }
+
+    #[test]
+    fn test_verify() {
+        let (lch, blocks) = setup(3);
+        let tx = &blocks[1].txs[2];
+        let spv_proof = SpvProof::from_block(&blocks[1], &tx.id()).unwrap();
+        assert_eq!(spv_proof.verify_tx(tx, &lch, 2).unwrap(), 1);
+        assert!(spv_proof.verify_tx(tx, &lch, 3).is_err());
+
+        // another tx, or the same tx in another block, fails
+        let other_tx = &blocks[1].txs[3];
+        assert!(spv_proof.verify_tx(other_tx, &lch, 1).is_err());
+        let mut wrong_block = spv_proof.clone();
+        wrong_block.block_id = blocks[2].header.id();
+        assert!(wrong_block.verify_tx(tx, &lch, 1).is_err());
+        let mut unknown_block = spv_proof.clone();
+        unknown_block.block_id = [0; 32];
+        assert!(unknown_block.verify_tx(tx, &lch, 1).is_err());
+
+        // a proof for a tx in the tip
+        let tx = &blocks[2].txs[0];
+        let spv_proof = SpvProof::from_block(&blocks[2], &tx.id()).unwrap();
+        assert_eq!(spv_proof.verify_tx(tx, &lch, 1).unwrap(), 2);
+        assert!(SpvProof::from_block(&blocks[2], &[0; 32]).is_none());
+    }
+
+    #[test]
+    fn test_to_strict_str_and_from_strict_str() {
+        let (lch, blocks) = setup(1);
+        let tx = &blocks[0].txs[3];
+        let spv_proof = SpvProof::from_block(&blocks[0], &tx.id()).unwrap();
+        let decoded = SpvProof::from_strict_str(&spv_proof.to_strict_str()).unwrap();
+        assert_eq!(decoded.to_buf(), spv_proof.to_buf());
+        assert_eq!(decoded.verify_tx(tx, &lch, 1).unwrap(), 0);
+
+        let mut buf = spv_proof.to_buf();
+        buf.push(0);
+        assert!(SpvProof::from_buf(buf).is_err());
+    }
+}
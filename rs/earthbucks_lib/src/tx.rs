@@ -6,6 +6,7 @@ use crate::hash::blake3_hash;
 use crate::hash::double_blake3_hash;
 use crate::pub_key::PubKey;
 use crate::script::Script;
+use crate::sig_cache::SigCache;
 use crate::tx_in::TxIn;
 use crate::tx_out::TxOut;
 use crate::tx_signature::TxSignature;
@@ -105,6 +106,22 @@ impl Tx {
         Self::from_strict_hex(hex)
     }
 
+    // the size of the tx once all inputs are signed. TxBuilder fills inputs
+    // with placeholders of the same size as the signed scripts, so this can be
+    // called before signing.
+    pub fn estimated_signed_size(&self) -> usize {
+        let mut size = 1;
+        size += VarInt::from_u64(self.inputs.len() as u64).to_buf().len();
+        for input in &self.inputs {
+            size += input.estimated_signed_size();
+        }
+        size += VarInt::from_u64(self.outputs.len() as u64).to_buf().len();
+        for output in &self.outputs {
+            size += output.to_buf().len();
+        }
+        size + 4
+    }
+
     pub fn from_coinbase(
         input_script: Script,
         output_script: Script,
@@ -223,7 +240,7 @@ impl Tx {
     }
 
     pub fn sighash_no_cache(
-        &mut self,
+        &self,
         input_index: usize,
         script_iso_buf: Vec<u8>,
         amount: u64,
@@ -245,7 +262,7 @@ impl Tx {
     }
 
     pub fn sighash_with_cache(
-        &mut self,
+        &self,
         input_index: usize,
         script_iso_buf: Vec<u8>,
         amount: u64,
@@ -258,7 +275,7 @@ impl Tx {
     }
 
     pub fn sign_no_cache(
-        &mut self,
+        &self,
         input_index: usize,
         private_key: [u8; 32],
         script: Vec<u8>,
@@ -280,7 +297,7 @@ impl Tx {
     }
 
     pub fn sign_with_cache(
-        &mut self,
+        &self,
         input_index: usize,
         private_key: [u8; 32],
         script: Vec<u8>,
@@ -304,29 +321,19 @@ impl Tx {
     }
 
     pub fn verify_no_cache(
-        &mut self,
+        &self,
         input_index: usize,
         public_key: [u8; PubKey::SIZE],
         signature: TxSignature,
         script: Vec<u8>,
         amount: u64,
     ) -> bool {
-        let hash_type = signature.hash_type;
-        let secp = Secp256k1::new();
-        let pubkey = PublicKey::from_slice(&public_key).expect("33 bytes");
-        let message = Message::from_digest_slice(&self.sighash_no_cache(
-            input_index,
-            script,
-            amount,
-            hash_type,
-        ))
-        .expect("32 bytes");
-        let signature = Signature::from_compact(&signature.sig_buf).expect("64 bytes");
-        secp.verify_ecdsa(&message, &signature, &pubkey).is_ok()
+        let sighash = self.sighash_no_cache(input_index, script, amount, signature.hash_type);
+        Tx::verify_sighash(&sighash, public_key, &signature, None)
     }
 
     pub fn verify_with_cache(
-        &mut self,
+        &self,
         input_index: usize,
         public_key: [u8; PubKey::SIZE],
         signature: TxSignature,
@@ -334,19 +341,38 @@ impl Tx {
         amount: u64,
         hash_cache: &mut HashCache,
     ) -> bool {
-        let hash_type = signature.hash_type;
+        let sighash =
+            self.sighash_with_cache(input_index, script, amount, signature.hash_type, hash_cache);
+        Tx::verify_sighash(&sighash, public_key, &signature, None)
+    }
+
+    // verifies signature of sighash. with a sig_cache, signatures already in
+    // it are not verified again, and valid signatures are added to it.
+    pub fn verify_sighash(
+        sighash: &[u8; 32],
+        public_key: [u8; PubKey::SIZE],
+        signature: &TxSignature,
+        sig_cache: Option<&SigCache>,
+    ) -> bool {
+        let sig_buf = signature.to_buf();
+        if let Some(sig_cache) = sig_cache {
+            if sig_cache.contains(sighash, &public_key, &sig_buf) {
+                return true;
+            }
+        }
         let secp = Secp256k1::new();
         let pubkey = PublicKey::from_slice(&public_key).expect("33 bytes");
-        let message = Message::from_digest_slice(&self.sighash_with_cache(
-            input_index,
-            script,
-            amount,
-            hash_type,
-            hash_cache,
-        ))
-        .expect("32 bytes");
-        let signature = Signature::from_compact(&signature.sig_buf).expect("64 bytes");
-        secp.verify_ecdsa(&message, &signature, &pubkey).is_ok()
+        let message = Message::from_digest_slice(sighash).expect("32 bytes");
+        let ecdsa_signature = Signature::from_compact(&signature.sig_buf).expect("64 bytes");
+        let success = secp
+            .verify_ecdsa(&message, &ecdsa_signature, &pubkey)
+            .is_ok();
+        if success {
+            if let Some(sig_cache) = sig_cache {
+                sig_cache.insert(sighash, &public_key, &sig_buf);
+            }
+        }
+        success
     }
 }
 
@@ -585,7 +611,7 @@ mod tests {
         let inputs = vec![TxIn::new([0; 32], 0, Script::from_empty(), 0)];
         let outputs = vec![TxOut::new(100, Script::from_empty())];
 
-        let mut tx = Tx::new(version, inputs, outputs, 0);
+        let tx = Tx::new(version, inputs, outputs, 0);
 
         let script = Script::from_empty();
         let amount = 1;
@@ -605,7 +631,7 @@ mod tests {
         let inputs = vec![TxIn::new([0; 32], 0, Script::from_empty(), 0)];
         let outputs = vec![TxOut::new(100, Script::from_empty())];
 
-        let mut tx = Tx::new(version, inputs, outputs, 0);
+        let tx = Tx::new(version, inputs, outputs, 0);
 
         let script = Script::from_empty();
         let amount = 1;
@@ -636,7 +662,7 @@ mod tests {
         );
         let outputs = vec![TxOut::new(100, Script::from_empty())];
         assert_eq!(hex::encode(outputs[0].to_buf()), "000000000000006400");
-        let mut tx = Tx::new(0, inputs, outputs, 0);
+        let tx = Tx::new(0, inputs, outputs, 0);
         assert_eq!(hex::encode(tx.to_buf()), "000100000000000000000000000000000000000000000000000000000000000000000000000000000000000100000000000000640000000000");
 
         let signature = tx.sign_no_cache(
@@ -676,7 +702,7 @@ mod tests {
         );
         let outputs = vec![TxOut::new(100, Script::from_empty())];
         assert_eq!(hex::encode(outputs[0].to_buf()), "000000000000006400");
-        let mut tx = Tx::new(0, inputs, outputs, 0);
+        let tx = Tx::new(0, inputs, outputs, 0);
         assert_eq!(hex::encode(tx.to_buf()), "000100000000000000000000000000000000000000000000000000000000000000000000000000000000000100000000000000640000000000");
         let hash_cache_1 = &mut HashCache::new();
 
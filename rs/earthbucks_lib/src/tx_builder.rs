@@ -1,8 +1,10 @@
 use crate::error::EbxError;
 use crate::script::Script;
+use crate::script_template::ScriptTemplates;
 use crate::tx::Tx;
 use crate::tx_in::TxIn;
 use crate::tx_out::TxOut;
+use crate::tx_out_bn::TxOutBn;
 use crate::tx_out_bn_map::TxOutBnMap;
 
 pub struct TxBuilder {
@@ -11,6 +13,8 @@ pub struct TxBuilder {
     change_script: Script,
     input_amount: u64,
     lock_abs: u32,
+    max_tx_size: Option<usize>,
+    script_templates: ScriptTemplates,
 }
 
 impl TxBuilder {
@@ -21,9 +25,23 @@ impl TxBuilder {
             change_script,
             input_amount: 0,
             lock_abs,
+            max_tx_size: None,
+            script_templates: ScriptTemplates::from_standard(),
         }
     }
 
+    // the templates used to recognize spendable outputs and create their
+    // input placeholders. defaults to the standard output types.
+    pub fn set_script_templates(&mut self, script_templates: ScriptTemplates) {
+        self.script_templates = script_templates;
+    }
+
+    // maximum size of a built tx once signed. build fails if the tx would be
+    // larger, and build_split splits the outputs across several txs.
+    pub fn set_max_tx_size(&mut self, max_tx_size: usize) {
+        self.max_tx_size = Some(max_tx_size);
+    }
+
     pub fn add_output(&mut self, tx_out: TxOut) {
         self.tx.outputs.push(tx_out);
     }
@@ -33,6 +51,68 @@ impl TxBuilder {
         self.input_amount += amount;
     }
 
+    fn spends(&self, tx_id: &[u8; 32], tx_out_num: u32) -> bool {
+        self.tx
+            .inputs
+            .iter()
+            .any(|input| &input.input_tx_id == tx_id && input.input_tx_out_num == tx_out_num)
+    }
+
+    // pins an output from the map as an input of the tx. this is coin
+    // control: build_manual spends only pinned inputs, build_sweep spends
+    // only pinned inputs if there are any, and build spends pinned inputs
+    // first before selecting more.
+    pub fn add_tx_out_bn_input(
+        &mut self,
+        tx_id: &[u8; 32],
+        tx_out_num: u32,
+    ) -> Result<(), EbxError> {
+        if self.spends(tx_id, tx_out_num) {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "input already added".to_string(),
+            });
+        }
+        let tx_out = match self.input_tx_out_bn_map.get(tx_id, tx_out_num) {
+            Some(tx_out_bn) => tx_out_bn.tx_out.clone(),
+            None => {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "tx_out not found".to_string(),
+                })
+            }
+        };
+        let input_script = self.input_placeholder(&tx_out)?;
+        self.add_input(TxIn::new(*tx_id, tx_out_num, input_script, 0), tx_out.value);
+        Ok(())
+    }
+
+    fn input_placeholder(&self, tx_out: &TxOut) -> Result<Script, EbxError> {
+        match self.script_templates.find(&tx_out.script) {
+            Some(template) => Ok(template.input_placeholder(&tx_out.script)),
+            None => Err(EbxError::GenericError {
+                source: None,
+                message: "unsupported script type".to_string(),
+            }),
+        }
+    }
+
+    // the utxos in the order of TxOutBnMap::sorted, which uses the "most
+    // confirmed" outputs first. outputs already spent by an input of the tx
+    // are skipped.
+    fn sorted_tx_out_bns(&self) -> Vec<(String, TxOutBn)> {
+        self.input_tx_out_bn_map
+            .sorted()
+            .into_iter()
+            .filter(|(tx_out_id, _)| {
+                let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(tx_out_id).try_into().unwrap();
+                let tx_out_num = TxOutBnMap::name_to_tx_out_num(tx_out_id);
+                !self.spends(&tx_id, tx_out_num)
+            })
+            .map(|(tx_out_id, tx_out_bn)| (tx_out_id.clone(), tx_out_bn.clone()))
+            .collect()
+    }
+
     // "tx fees", also called "change fees", are zero on earthbucks. this
     // simplifies the logic of building a tx. input must be exactly equal to
     // output to be valid. remainder goes to change, which is owned by the user.
@@ -40,51 +120,247 @@ impl TxBuilder {
     pub fn build(&mut self) -> Result<Tx, EbxError> {
         self.tx.lock_abs = self.lock_abs;
         let total_spend_amount: u64 = self.tx.outputs.iter().map(|output| output.value).sum();
-        let mut change_amount = 0;
         let mut input_amount = self.input_amount;
 
-        // sort by block number first, but if those are the same, sort by the id
-        // of the tx_out, which is tx_id plus tx_out_num together in a string.
-        // this logic means we use the "most confirmed" outputs first, which is
-        // what we want, and then we have a deterministic way to sort the UTXOs
-        // in the same block.
-        let mut sorted_tx_out_bns: Vec<_> = self.input_tx_out_bn_map.map.iter().collect();
-        sorted_tx_out_bns
-            .sort_by(|a, b| a.1.block_num.cmp(&b.1.block_num).then_with(|| a.0.cmp(b.0)));
-
-        for (tx_out_id, tx_out_bn) in sorted_tx_out_bns {
+        for (tx_out_id, tx_out_bn) in self.sorted_tx_out_bns() {
             if input_amount >= total_spend_amount {
-                change_amount = input_amount - total_spend_amount;
                 break;
             }
             let tx_out = &tx_out_bn.tx_out;
-            let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(tx_out_id).try_into().unwrap();
-            let tx_out_num = TxOutBnMap::name_to_tx_out_num(tx_out_id);
-
-            let input_script: Script = if tx_out.script.is_pkh_output() {
-                Script::from_pkh_input_placeholder()
-            } else if tx_out.script.is_pkhx_90d_output() || tx_out.script.is_pkhx_1h_output() {
-                Script::from_unexpired_pkhx_input_placeholder()
-            } else if tx_out.script.is_pkhxr_90d_60d_output()
-                || tx_out.script.is_pkhxr_1h_40m_output()
-            {
-                Script::from_unexpired_pkhxr_input_placeholder()
-            } else {
+            let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(&tx_out_id).try_into().unwrap();
+            let tx_out_num = TxOutBnMap::name_to_tx_out_num(&tx_out_id);
+
+            let input_script = self.input_placeholder(tx_out)?;
+            let tx_input = TxIn::new(tx_id, tx_out_num, input_script, 0);
+            self.tx.inputs.push(tx_input);
+            input_amount += tx_out.value;
+        }
+        self.input_amount = input_amount;
+        if input_amount > total_spend_amount {
+            let tx_out = TxOut::new(
+                input_amount - total_spend_amount,
+                self.change_script.clone(),
+            );
+            self.add_output(tx_out);
+        }
+        self.check_max_tx_size()?;
+        Ok(self.tx.clone())
+    }
+
+    fn check_max_tx_size(&self) -> Result<(), EbxError> {
+        if let Some(max_tx_size) = self.max_tx_size {
+            if self.tx.estimated_signed_size() > max_tx_size {
                 return Err(EbxError::GenericError {
                     source: None,
-                    message: "unsupported script type".to_string(),
+                    message: "tx too large".to_string(),
                 });
-            };
+            }
+        }
+        Ok(())
+    }
 
+    // manual mode spends only the inputs pinned with add_tx_out_bn_input or
+    // add_input and fails rather than selecting more. the remainder goes to
+    // change.
+    pub fn build_manual(&mut self) -> Result<Tx, EbxError> {
+        self.tx.lock_abs = self.lock_abs;
+        let total_spend_amount: u64 = self.tx.outputs.iter().map(|output| output.value).sum();
+        if self.input_amount < total_spend_amount {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "insufficient funds in pinned inputs".to_string(),
+            });
+        }
+        if self.input_amount > total_spend_amount {
+            let change_amount = self.input_amount - total_spend_amount;
+            let tx_out = TxOut::new(change_amount, self.change_script.clone());
+            self.add_output(tx_out);
+        }
+        self.check_max_tx_size()?;
+        Ok(self.tx.clone())
+    }
+
+    // sweep mode ("send max") spends the pinned inputs, or every output in the
+    // map if none are pinned, and sends everything not paid to the outputs
+    // added so far to sweep_script. there is no change output.
+    pub fn build_sweep(&mut self, sweep_script: Script) -> Result<Tx, EbxError> {
+        self.tx.lock_abs = self.lock_abs;
+        if self.tx.inputs.is_empty() {
+            for (tx_out_id, tx_out_bn) in self.sorted_tx_out_bns() {
+                let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(&tx_out_id).try_into().unwrap();
+                let tx_out_num = TxOutBnMap::name_to_tx_out_num(&tx_out_id);
+                let input_script = self.input_placeholder(&tx_out_bn.tx_out)?;
+                let tx_input = TxIn::new(tx_id, tx_out_num, input_script, 0);
+                self.add_input(tx_input, tx_out_bn.tx_out.value);
+            }
+        }
+        let total_spend_amount: u64 = self.tx.outputs.iter().map(|output| output.value).sum();
+        if self.tx.inputs.is_empty() || self.input_amount <= total_spend_amount {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "nothing to sweep".to_string(),
+            });
+        }
+        let sweep_amount = self.input_amount - total_spend_amount;
+        self.add_output(TxOut::new(sweep_amount, sweep_script));
+        self.check_max_tx_size()?;
+        Ok(self.tx.clone())
+    }
+
+    // like build, but the outputs added so far are spread across as many txs
+    // as needed to keep each one within the max tx size. outputs keep their
+    // order, each tx spends the next utxos in the usual order and gets its own
+    // change output. inputs added with add_input go into the first tx.
+    pub fn build_split(&mut self) -> Result<Vec<Tx>, EbxError> {
+        let max_tx_size = self.max_tx_size.unwrap_or(usize::MAX);
+        let payments = self.tx.outputs.clone();
+
+        let mut utxo_inputs: Vec<(TxIn, u64)> = Vec::new();
+        for (tx_out_id, tx_out_bn) in self.sorted_tx_out_bns() {
+            let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(&tx_out_id).try_into().unwrap();
+            let tx_out_num = TxOutBnMap::name_to_tx_out_num(&tx_out_id);
+            let input_script = self.input_placeholder(&tx_out_bn.tx_out)?;
             let tx_input = TxIn::new(tx_id, tx_out_num, input_script, 0);
+            utxo_inputs.push((tx_input, tx_out_bn.tx_out.value));
+        }
+
+        let mut txs = Vec::new();
+        let mut tx = Tx::new(0, self.tx.inputs.clone(), vec![], self.lock_abs);
+        let mut input_amount = self.input_amount;
+        let mut output_amount = 0;
+        let mut next_utxo = 0;
+        let mut next_payment = 0;
+        while next_payment < payments.len() {
+            let payment = &payments[next_payment];
+            let prev_inputs_len = tx.inputs.len();
+            let prev_input_amount = input_amount;
+            let prev_next_utxo = next_utxo;
+
+            tx.outputs.push(payment.clone());
+            output_amount += payment.value;
+            while input_amount < output_amount && next_utxo < utxo_inputs.len() {
+                let (tx_input, amount) = &utxo_inputs[next_utxo];
+                tx.inputs.push(tx_input.clone());
+                input_amount += amount;
+                next_utxo += 1;
+            }
+            if input_amount < output_amount {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "insufficient funds".to_string(),
+                });
+            }
+
+            let change_amount = input_amount - output_amount;
+            if change_amount > 0 {
+                tx.outputs
+                    .push(TxOut::new(change_amount, self.change_script.clone()));
+            }
+            let size = tx.estimated_signed_size();
+            if change_amount > 0 {
+                tx.outputs.pop();
+            }
+            if size <= max_tx_size {
+                next_payment += 1;
+                continue;
+            }
+
+            // this output does not fit. finish the current tx without it and
+            // try it again in a new one.
+            tx.outputs.pop();
+            tx.inputs.truncate(prev_inputs_len);
+            input_amount = prev_input_amount;
+            output_amount -= payment.value;
+            next_utxo = prev_next_utxo;
+            if tx.outputs.is_empty() {
+                return Err(EbxError::GenericError {
+                    source: None,
+                    message: "output does not fit in max tx size".to_string(),
+                });
+            }
+            if input_amount > output_amount {
+                let tx_out = TxOut::new(input_amount - output_amount, self.change_script.clone());
+                tx.outputs.push(tx_out);
+            }
+            txs.push(tx);
+            tx = Tx::new(0, vec![], vec![], self.lock_abs);
+            input_amount = 0;
+            output_amount = 0;
+        }
+        if !tx.outputs.is_empty() {
+            if input_amount > output_amount {
+                let tx_out = TxOut::new(input_amount - output_amount, self.change_script.clone());
+                tx.outputs.push(tx_out);
+            }
+            txs.push(tx);
+        }
+        Ok(txs)
+    }
+
+    // recovery spends pkhxr outputs through the recovery branch, i.e. with the
+    // key for rpkh rather than pkh. every output in the map whose rpkh matches
+    // and whose recovery window is open (recovery lock passed, not yet
+    // expired) is spent. the value pays for any outputs added so far and the
+    // remainder is swept to the change script, which is the new address.
+    pub fn build_recovery(
+        &mut self,
+        rpkh: &[u8; 32],
+        working_block_num: u32,
+    ) -> Result<Tx, EbxError> {
+        self.tx.lock_abs = self.lock_abs;
+        let total_spend_amount: u64 = self.tx.outputs.iter().map(|output| output.value).sum();
+        let mut input_amount = self.input_amount;
+        let mut recovered_count = 0;
+
+        for (tx_out_id, tx_out_bn) in self.sorted_tx_out_bns() {
+            let tx_out = &tx_out_bn.tx_out;
+            let prev_block_num = tx_out_bn.block_num;
+
+            let recovery_input = self
+                .script_templates
+                .find(&tx_out.script)
+                .and_then(|template| {
+                    template.recovery_input_placeholder(
+                        &tx_out.script,
+                        rpkh,
+                        prev_block_num,
+                        working_block_num,
+                    )
+                });
+            let (input_script, lock_rel) = match recovery_input {
+                Some(recovery_input) => recovery_input,
+                None => continue,
+            };
+
+            let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(&tx_out_id).try_into().unwrap();
+            let tx_out_num = TxOutBnMap::name_to_tx_out_num(&tx_out_id);
+            let tx_input = TxIn::new(tx_id, tx_out_num, input_script, lock_rel);
             self.tx.inputs.push(tx_input);
             input_amount += tx_out.value;
+            recovered_count += 1;
+        }
+
+        if recovered_count == 0 {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "no recoverable outputs".to_string(),
+            });
         }
         self.input_amount = input_amount;
-        if change_amount > 0 {
-            let tx_out = TxOut::new(change_amount, self.change_script.clone());
+        if input_amount < total_spend_amount {
+            return Err(EbxError::GenericError {
+                source: None,
+                message: "insufficient funds".to_string(),
+            });
+        }
+        if input_amount > total_spend_amount {
+            let tx_out = TxOut::new(
+                input_amount - total_spend_amount,
+                self.change_script.clone(),
+            );
             self.add_output(tx_out);
         }
+        self.check_max_tx_size()?;
         Ok(self.tx.clone())
     }
 }
@@ -94,7 +370,12 @@ mod tests {
     use super::*;
     use crate::key_pair::KeyPair;
     use crate::pkh::Pkh;
+    use crate::pkh_key_map::PkhKeyMap;
     use crate::script::Script;
+    use crate::script_template::PkhxTemplate;
+    use crate::tx_signer::TxSigner;
+    use crate::tx_verifier::TxVerifier;
+    use std::sync::Arc;
 
     fn setup() -> TxBuilder {
         let mut tx_out_bn_map = TxOutBnMap::new();
@@ -138,4 +419,260 @@ mod tests {
         assert_eq!(tx_builder.input_amount, 500);
         assert_eq!(tx.outputs[0].value, 10000);
     }
+
+    #[test]
+    fn test_build_recovery_sweeps_recoverable_outputs_to_change() {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let mut pkh_key_map = PkhKeyMap::new();
+        let recovery_key = KeyPair::from_random();
+        let rpkh = Pkh::from_pub_key_buffer(recovery_key.pub_key.buf.to_vec());
+        pkh_key_map.add(recovery_key.clone(), &rpkh.buf);
+        let other_rpkh = Pkh::from_pub_key_buffer(KeyPair::from_random().pub_key.buf.to_vec());
+
+        // recoverable
+        let script = Script::from_pkhxr_90d_60d_output(&[0; 32], &rpkh.buf);
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, script), 0);
+        let script = Script::from_pkhxr_1h_40m_output(&[0; 32], &rpkh.buf);
+        tx_out_bn_map.add(&[2; 32], 0, TxOut::new(200, script), 8640 - 4);
+        // recovery window not open yet
+        let script = Script::from_pkhxr_90d_60d_output(&[0; 32], &rpkh.buf);
+        tx_out_bn_map.add(&[3; 32], 0, TxOut::new(400, script), 1);
+        // expired
+        let script = Script::from_pkhxr_1h_40m_output(&[0; 32], &rpkh.buf);
+        tx_out_bn_map.add(&[4; 32], 0, TxOut::new(800, script), 0);
+        // different recovery key
+        let script = Script::from_pkhxr_90d_60d_output(&[0; 32], &other_rpkh.buf);
+        tx_out_bn_map.add(&[5; 32], 0, TxOut::new(1600, script), 0);
+
+        let working_block_num = Script::PKHXR_90D_60D_R_LOCK_REL;
+        let new_pkh = Pkh::from_pub_key_buffer(KeyPair::from_random().pub_key.buf.to_vec());
+        let change_script = Script::from_pkh_output(&new_pkh.buf);
+        let mut tx_builder = TxBuilder::new(&tx_out_bn_map, change_script.clone(), 0);
+        let tx = tx_builder
+            .build_recovery(&rpkh.buf, working_block_num)
+            .unwrap();
+
+        assert_eq!(tx.inputs.len(), 2);
+        assert!(tx.inputs[0].script.is_recovery_pkhxr_input());
+        assert_eq!(tx.inputs[0].input_tx_id, [1; 32]);
+        assert_eq!(tx.inputs[0].lock_rel, Script::PKHXR_90D_60D_R_LOCK_REL);
+        assert_eq!(tx.inputs[1].input_tx_id, [2; 32]);
+        assert_eq!(tx.inputs[1].lock_rel, Script::PKHXR_1H_40M_R_LOCK_REL);
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(tx.outputs[0].value, 300);
+        assert_eq!(tx.outputs[0].script, change_script);
+
+        let mut tx_signer = TxSigner::new(tx, &tx_out_bn_map, &pkh_key_map, working_block_num);
+        let signed_tx = tx_signer.sign().unwrap();
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, working_block_num);
+        assert!(tx_verifier.verify());
+    }
+
+    #[test]
+    fn test_build_recovery_fails_on_insufficient_funds() {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let script = Script::from_pkhxr_90d_60d_output(&[0; 32], &[1; 32]);
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(100, script), 0);
+        let mut tx_builder = TxBuilder::new(&tx_out_bn_map, Script::from_empty(), 0);
+        tx_builder.add_output(TxOut::new(101, Script::from_empty()));
+        let res = tx_builder.build_recovery(&[1; 32], Script::PKHXR_90D_60D_R_LOCK_REL);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_build_recovery_fails_without_recoverable_outputs() {
+        let mut tx_builder = setup();
+        let res = tx_builder.build_recovery(&[0; 32], Script::PKHXR_90D_60D_R_LOCK_REL);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_estimated_signed_size_matches_signed_tx() {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let mut pkh_key_map = PkhKeyMap::new();
+        let scripts = |pkh: &[u8; 32]| {
+            vec![
+                Script::from_pkh_output(pkh),
+                Script::from_pkhx_1h_output(pkh),
+                Script::from_pkhxr_90d_60d_output(pkh, pkh),
+            ]
+        };
+        for i in 0..3 {
+            let key = KeyPair::from_random();
+            let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec());
+            pkh_key_map.add(key, &pkh.buf);
+            let script = scripts(&pkh.buf)[i].clone();
+            tx_out_bn_map.add(&[0; 32], i as u32, TxOut::new(100, script), 0);
+        }
+        let mut tx_builder = TxBuilder::new(&tx_out_bn_map, Script::from_empty(), 0);
+        tx_builder.add_output(TxOut::new(250, Script::from_empty()));
+        let tx = tx_builder.build().unwrap();
+        assert_eq!(tx.inputs.len(), 3);
+        let estimated_size = tx.estimated_signed_size();
+
+        let mut tx_signer = TxSigner::new(tx, &tx_out_bn_map, &pkh_key_map, 0);
+        let signed_tx = tx_signer.sign().unwrap();
+        assert_eq!(signed_tx.to_buf().len(), estimated_size);
+        assert_eq!(signed_tx.estimated_signed_size(), estimated_size);
+    }
+
+    #[test]
+    fn test_build_fails_when_tx_too_large() {
+        let mut tx_builder = setup();
+        tx_builder.set_max_tx_size(200);
+        tx_builder.add_output(TxOut::new(300, Script::from_empty()));
+        assert!(tx_builder.build().is_err());
+    }
+
+    #[test]
+    fn test_build_split_splits_outputs_by_size() {
+        let mut tx_builder = setup();
+        let change_script = Script::from_pkh_output(&[1; 32]);
+        tx_builder.change_script = change_script.clone();
+        for _ in 0..4 {
+            tx_builder.add_output(TxOut::new(60, Script::from_pkh_output(&[2; 32])));
+        }
+        let one_input_two_outputs = Tx::new(
+            0,
+            vec![TxIn::new(
+                [0; 32],
+                0,
+                Script::from_pkh_input_placeholder(),
+                0,
+            )],
+            vec![
+                TxOut::new(60, Script::from_pkh_output(&[2; 32])),
+                TxOut::new(40, change_script.clone()),
+            ],
+            0,
+        )
+        .estimated_signed_size();
+        tx_builder.set_max_tx_size(one_input_two_outputs);
+
+        let txs = tx_builder.build_split().unwrap();
+        assert_eq!(txs.len(), 4);
+        for tx in &txs {
+            assert!(tx.estimated_signed_size() <= one_input_two_outputs);
+            let input_amount = tx.inputs.len() as u64 * 100;
+            let output_amount: u64 = tx.outputs.iter().map(|output| output.value).sum();
+            assert_eq!(input_amount, output_amount);
+        }
+        assert_eq!(txs[0].inputs.len(), 1);
+        assert_eq!(txs[0].outputs[1].script, change_script);
+        assert_eq!(txs[1].inputs[0].input_tx_out_num, 1);
+    }
+
+    #[test]
+    fn test_build_split_without_limit_builds_one_tx() {
+        let mut tx_builder = setup();
+        tx_builder.add_output(TxOut::new(150, Script::from_empty()));
+        tx_builder.add_output(TxOut::new(100, Script::from_empty()));
+        let txs = tx_builder.build_split().unwrap();
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].inputs.len(), 3);
+        assert_eq!(txs[0].outputs.len(), 3);
+        assert_eq!(txs[0].outputs[2].value, 50);
+    }
+
+    #[test]
+    fn test_build_split_fails_on_insufficient_funds() {
+        let mut tx_builder = setup();
+        tx_builder.add_output(TxOut::new(600, Script::from_empty()));
+        assert!(tx_builder.build_split().is_err());
+    }
+
+    #[test]
+    fn test_build_adds_change_when_all_utxos_are_spent() {
+        let mut tx_builder = setup();
+        let tx_out = TxOut::new(450, Script::from_empty());
+        tx_builder.add_output(tx_out);
+
+        let tx = tx_builder.build().unwrap();
+
+        assert_eq!(tx.inputs.len(), 5);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].value, 50);
+    }
+
+    #[test]
+    fn test_build_sweep_spends_every_utxo_without_change() {
+        let mut tx_builder = setup();
+        let sweep_script = Script::from_pkh_output(&[1; 32]);
+        let tx = tx_builder.build_sweep(sweep_script.clone()).unwrap();
+
+        assert_eq!(tx.inputs.len(), 5);
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(tx.outputs[0].value, 500);
+        assert_eq!(tx.outputs[0].script, sweep_script);
+    }
+
+    #[test]
+    fn test_build_sweep_spends_pinned_utxos_only() {
+        let mut tx_builder = setup();
+        tx_builder.add_tx_out_bn_input(&[0; 32], 1).unwrap();
+        tx_builder.add_tx_out_bn_input(&[0; 32], 3).unwrap();
+        tx_builder.add_output(TxOut::new(30, Script::from_empty()));
+        let sweep_script = Script::from_pkh_output(&[1; 32]);
+        let tx = tx_builder.build_sweep(sweep_script.clone()).unwrap();
+
+        assert_eq!(tx.inputs.len(), 2);
+        assert_eq!(tx.inputs[0].input_tx_out_num, 1);
+        assert_eq!(tx.inputs[1].input_tx_out_num, 3);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[0].value, 30);
+        assert_eq!(tx.outputs[1].value, 170);
+        assert_eq!(tx.outputs[1].script, sweep_script);
+    }
+
+    #[test]
+    fn test_build_manual_spends_pinned_utxos_only() {
+        let mut tx_builder = setup();
+        tx_builder.add_tx_out_bn_input(&[0; 32], 4).unwrap();
+        tx_builder.add_output(TxOut::new(60, Script::from_empty()));
+        let tx = tx_builder.build_manual().unwrap();
+
+        assert_eq!(tx.inputs.len(), 1);
+        assert_eq!(tx.inputs[0].input_tx_out_num, 4);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].value, 40);
+    }
+
+    #[test]
+    fn test_build_manual_fails_rather_than_adding_inputs() {
+        let mut tx_builder = setup();
+        tx_builder.add_tx_out_bn_input(&[0; 32], 4).unwrap();
+        tx_builder.add_output(TxOut::new(150, Script::from_empty()));
+        assert!(tx_builder.build_manual().is_err());
+    }
+
+    #[test]
+    fn test_add_tx_out_bn_input_rejects_unknown_and_duplicate_outputs() {
+        let mut tx_builder = setup();
+        assert!(tx_builder.add_tx_out_bn_input(&[1; 32], 0).is_err());
+        tx_builder.add_tx_out_bn_input(&[0; 32], 0).unwrap();
+        assert!(tx_builder.add_tx_out_bn_input(&[0; 32], 0).is_err());
+    }
+
+    #[test]
+    fn test_build_does_not_reselect_pinned_utxo() {
+        let mut tx_builder = setup();
+        tx_builder.add_tx_out_bn_input(&[0; 32], 0).unwrap();
+        tx_builder.add_output(TxOut::new(150, Script::from_empty()));
+        let tx = tx_builder.build().unwrap();
+
+        assert_eq!(tx.inputs.len(), 2);
+        assert_eq!(tx.inputs[0].input_tx_out_num, 0);
+        assert_eq!(tx.inputs[1].input_tx_out_num, 1);
+    }
+
+    #[test]
+    fn test_build_only_spends_outputs_with_a_registered_template() {
+        let mut tx_builder = setup();
+        let mut script_templates = ScriptTemplates::new();
+        script_templates.add(Arc::new(PkhxTemplate::from_pkhx_1h()));
+        tx_builder.set_script_templates(script_templates);
+        tx_builder.add_output(TxOut::new(50, Script::from_empty()));
+        assert!(tx_builder.build().is_err());
+        assert!(tx_builder.add_tx_out_bn_input(&[0; 32], 0).is_err());
+    }
 }
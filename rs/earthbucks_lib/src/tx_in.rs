@@ -58,6 +58,11 @@ impl TxIn {
         writer.to_buf()
     }
 
+    pub fn estimated_signed_size(&self) -> usize {
+        let script_size = self.script.signed_input_size();
+        32 + 4 + VarInt::from_u64(script_size as u64).to_buf().len() + script_size + 4
+    }
+
     pub fn is_null(&self) -> bool {
         self.input_tx_id.iter().all(|&byte| byte == 0) && self.input_tx_out_num == 0xffffffff
     }
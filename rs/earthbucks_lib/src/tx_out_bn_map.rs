@@ -1,18 +1,23 @@
 use crate::buf::EbxBuf;
+use crate::sealed_tx::SealedTx;
 use crate::tx::Tx;
 use crate::tx_out::TxOut;
 use crate::tx_out_bn::TxOutBn;
+use crate::utxo_commitment::UtxoCommitment;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Default)]
 pub struct TxOutBnMap {
-    pub map: HashMap<String, TxOutBn>,
+    map: HashMap<String, TxOutBn>,
+    // kept up to date by add and remove
+    commitment: UtxoCommitment,
 }
 
 impl TxOutBnMap {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            commitment: UtxoCommitment::new(),
         }
     }
 
@@ -34,12 +39,23 @@ impl TxOutBnMap {
             tx_out: tx_out.clone(),
             block_num,
         };
-        self.map.insert(name, tx_out_bn);
+        self.commitment.add(tx_id, tx_out_num, &tx_out_bn);
+        if let Some(old) = self.map.insert(name, tx_out_bn) {
+            self.commitment.remove(tx_id, tx_out_num, &old);
+        }
     }
 
     pub fn remove(&mut self, tx_id: &[u8; 32], tx_out_num: u32) {
         let name = Self::name_from_output(tx_id, tx_out_num);
-        self.map.remove(&name);
+        if let Some(old) = self.map.remove(&name) {
+            self.commitment.remove(tx_id, tx_out_num, &old);
+        }
+    }
+
+    // the commitment to the outputs in the map, updated as they are added and
+    // removed. see UtxoCommitment::from_tx_out_bn_map to compute it again.
+    pub fn commitment(&self) -> &UtxoCommitment {
+        &self.commitment
     }
 
     pub fn get(&self, tx_id: &[u8; 32], tx_out_num: u32) -> Option<&TxOutBn> {
@@ -51,9 +67,44 @@ impl TxOutBnMap {
         self.map.values().collect()
     }
 
+    // the outputs by name, see name_from_output
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &TxOutBn)> {
+        self.map.iter()
+    }
+
+    // sort by block number first, but if those are the same, sort by the id
+    // of the tx_out, which is tx_id plus tx_out_num together in a string.
+    // this gives the oldest outputs first, and a deterministic order for the
+    // outputs in the same block.
+    pub fn sorted(&self) -> Vec<(&String, &TxOutBn)> {
+        let mut sorted: Vec<(&String, &TxOutBn)> = self.map.iter().collect();
+        sorted.sort_by(|a, b| a.1.block_num.cmp(&b.1.block_num).then_with(|| a.0.cmp(b.0)));
+        sorted
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.map.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
     pub fn add_tx_outputs(&mut self, tx: &Tx, block_num: u32) {
+        self.add_outputs(&tx.id(), tx, block_num);
+    }
+
+    pub fn add_sealed_tx_outputs(&mut self, sealed_tx: &SealedTx, block_num: u32) {
+        self.add_outputs(&sealed_tx.id(), sealed_tx.tx(), block_num);
+    }
+
+    fn add_outputs(&mut self, tx_id: &[u8; 32], tx: &Tx, block_num: u32) {
         for (output_index, output) in tx.outputs.iter().enumerate() {
-            self.add(&tx.id(), output_index as u32, output.clone(), block_num);
+            self.add(tx_id, output_index as u32, output.clone(), block_num);
         }
     }
 }
@@ -162,4 +213,32 @@ mod tests {
         assert!(values.contains(&&tx_out_bn1));
         assert!(values.contains(&&tx_out_bn2));
     }
+
+    #[test]
+    fn test_commitment() {
+        let mut tx_out_map = TxOutBnMap::new();
+        let empty_hash = tx_out_map.commitment().hash();
+        for i in 0..10u8 {
+            let tx_out = TxOut::new(i as u64, Script::from_empty());
+            tx_out_map.add(&[i; 32], 0, tx_out.clone(), 1);
+            tx_out_map.add(&[i; 32], 1, tx_out, 1);
+        }
+        // replacing an output and removing a missing one keep it in sync
+        tx_out_map.add(&[0; 32], 0, TxOut::new(100, Script::from_empty()), 2);
+        tx_out_map.remove(&[3; 32], 1);
+        tx_out_map.remove(&[3; 32], 1);
+        tx_out_map.remove(&[20; 32], 0);
+        assert_eq!(
+            *tx_out_map.commitment(),
+            UtxoCommitment::from_tx_out_bn_map(&tx_out_map)
+        );
+        assert_eq!(tx_out_map.commitment().n_outputs(), 19);
+        assert_ne!(tx_out_map.commitment().hash(), empty_hash);
+
+        for i in 0..10u8 {
+            tx_out_map.remove(&[i; 32], 0);
+            tx_out_map.remove(&[i; 32], 1);
+        }
+        assert_eq!(tx_out_map.commitment().hash(), empty_hash);
+    }
 }
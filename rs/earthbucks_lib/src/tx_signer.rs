@@ -1,39 +1,39 @@
 use crate::error::EbxError;
-use crate::pkh_key_map::PkhKeyMap;
-use crate::script::Script;
+use crate::script_template::{ScriptTemplates, TemplateInput};
+use crate::signer::Signer;
 use crate::tx::Tx;
 use crate::tx_out_bn_map::TxOutBnMap;
-use crate::tx_signature::TxSignature;
 
-pub struct TxSigner {
+pub struct TxSigner<'a> {
     pub tx: Tx,
-    pub pkh_key_map: PkhKeyMap,
+    pub signer: &'a dyn Signer,
     pub tx_out_bn_map: TxOutBnMap,
     pub working_block_num: u32,
+    pub script_templates: ScriptTemplates,
 }
 
-impl TxSigner {
+impl<'a> TxSigner<'a> {
     pub fn new(
         tx: Tx,
         tx_out_bn_map: &TxOutBnMap,
-        pkh_key_map: &PkhKeyMap,
+        signer: &'a dyn Signer,
         working_block_num: u32,
     ) -> Self {
         Self {
             tx,
             tx_out_bn_map: tx_out_bn_map.clone(),
-            pkh_key_map: pkh_key_map.clone(),
+            signer,
             working_block_num,
+            script_templates: ScriptTemplates::from_standard(),
         }
     }
 
     pub fn sign_input(&mut self, n_in: usize) -> Result<Tx, EbxError> {
-        let mut tx_clone = self.tx.clone();
-
-        let tx_input = &mut self.tx.inputs[n_in];
-        let tx_out_hash: &[u8; 32] = &tx_input.input_tx_id.clone();
-        let output_index = tx_input.input_tx_out_num;
-        let tx_out_bn = match self.tx_out_bn_map.get(tx_out_hash, output_index) {
+        let tx_input = &self.tx.inputs[n_in];
+        let tx_out_bn = match self
+            .tx_out_bn_map
+            .get(&tx_input.input_tx_id, tx_input.input_tx_out_num)
+        {
             Some(tx_out_bn) => tx_out_bn.clone(),
             None => {
                 return Err(EbxError::GenericError {
@@ -42,307 +42,27 @@ impl TxSigner {
                 })
             }
         };
-        let tx_out = tx_out_bn.tx_out;
-        let prev_block_num = tx_out_bn.block_num;
-
-        if tx_out.script.is_pkh_output() {
-            let pkh_buf: [u8; 32] = tx_out.script.chunks[2]
-                .buffer
-                .clone()
-                .expect("pkh not found")
-                .try_into()
-                .unwrap();
-            let input_script = &mut tx_input.script;
-            if !input_script.is_pkh_input() {
-                return Err(EbxError::GenericError {
-                    source: None,
-                    message: "expected pkh input placeholder".to_string(),
-                });
-            }
-            let key_pair = match self.pkh_key_map.get(&pkh_buf) {
-                Some(key) => key,
-                None => {
-                    return Err(EbxError::GenericError {
-                        source: None,
-                        message: "key not found".to_string(),
-                    })
-                }
-            };
-            let pub_key_buf = &key_pair.pub_key.buf.to_vec();
-            let output_script_buf = tx_out.script.to_buf();
-            let output_amount = tx_out.value;
-            let priv_key_buf = key_pair.priv_key.buf;
-            let sig = tx_clone.sign_no_cache(
-                n_in,
-                priv_key_buf,
-                output_script_buf.to_vec(),
-                output_amount,
-                TxSignature::SIGHASH_ALL,
-            );
-            let sig_buf = sig.to_buf();
-
-            input_script.chunks[0].buffer = Some(sig_buf.to_vec());
-            input_script.chunks[1].buffer = Some(pub_key_buf.clone());
-        } else if tx_out.script.is_pkhx_1h_output() {
-            let pkh_buf: [u8; 32] = tx_out.script.chunks[3]
-                .buffer
-                .clone()
-                .expect("pkh not found")
-                .try_into()
-                .unwrap();
-            let expired = Script::is_pkhx_1h_expired(self.working_block_num, prev_block_num);
-            let input_script = &mut tx_input.script;
-            if expired {
-                if input_script.is_expired_pkhx_input() {
-                    // no need to sign expired pkhx
-                    return Ok(self.tx.clone());
-                } else {
-                    return Err(EbxError::GenericError {
-                        source: None,
-                        message: "expected expired pkhx input".to_string(),
-                    });
-                }
-            }
-            if !input_script.is_unexpired_pkhx_input() {
-                return Err(EbxError::GenericError {
-                    source: None,
-                    message: "expected unexpired pkhx input placeholder".to_string(),
-                });
-            }
-            let key_pair = match self.pkh_key_map.get(&pkh_buf) {
-                Some(key) => key,
-                None => {
-                    return Err(EbxError::GenericError {
-                        source: None,
-                        message: "key not found".to_string(),
-                    })
-                }
-            };
-            let pub_key_buf = &key_pair.pub_key.buf.to_vec();
-            let output_script_buf = tx_out.script.to_buf();
-            let output_amount = tx_out.value;
-            let priv_key_buf = key_pair.priv_key.buf;
-            let sig = tx_clone.sign_no_cache(
-                n_in,
-                priv_key_buf,
-                output_script_buf.to_vec(),
-                output_amount,
-                TxSignature::SIGHASH_ALL,
-            );
-            let sig_buf = sig.to_buf();
-
-            input_script.chunks[0].buffer = Some(sig_buf.to_vec());
-            input_script.chunks[1].buffer = Some(pub_key_buf.clone());
-        } else if tx_out.script.is_pkhx_90d_output() {
-            let pkh_buf: [u8; 32] = tx_out.script.chunks[3]
-                .buffer
-                .clone()
-                .expect("pkh not found")
-                .try_into()
-                .unwrap();
-            let expired = Script::is_pkhx_90d_expired(self.working_block_num, prev_block_num);
-            let input_script = &mut tx_input.script;
-            if expired {
-                if input_script.is_expired_pkhx_input() {
-                    // no need to sign expired pkhx
-                    return Ok(self.tx.clone());
-                } else {
-                    return Err(EbxError::GenericError {
-                        source: None,
-                        message: "expected expired pkhx input".to_string(),
-                    });
-                }
-            }
-            if !input_script.is_unexpired_pkhx_input() {
-                return Err(EbxError::GenericError {
-                    source: None,
-                    message: "expected unexpired pkhx input placeholder".to_string(),
-                });
-            }
-            let key_pair = match self.pkh_key_map.get(&pkh_buf) {
-                Some(key) => key,
-                None => {
-                    return Err(EbxError::GenericError {
-                        source: None,
-                        message: "key not found".to_string(),
-                    })
-                }
-            };
-            let pub_key_buf = &key_pair.pub_key.buf.to_vec();
-            let output_script_buf = tx_out.script.to_buf();
-            let output_amount = tx_out.value;
-            let private_key_array = key_pair.priv_key.buf;
-            let sig = tx_clone.sign_no_cache(
-                n_in,
-                private_key_array,
-                output_script_buf.to_vec(),
-                output_amount,
-                TxSignature::SIGHASH_ALL,
-            );
-            let sig_buf = sig.to_buf();
-
-            input_script.chunks[0].buffer = Some(sig_buf.to_vec());
-            input_script.chunks[1].buffer = Some(pub_key_buf.clone());
-        } else if tx_out.script.is_pkhxr_1h_40m_output() {
-            let pkh_buf: [u8; 32] = tx_out.script.chunks[3]
-                .buffer
-                .clone()
-                .expect("pkh not found")
-                .try_into()
-                .unwrap();
-            let rpkh_buf: [u8; 32] = tx_out.script.chunks[13]
-                .buffer
-                .clone()
-                .expect("rpkh not found")
-                .try_into()
-                .unwrap();
-            let expired = Script::is_pkhxr_1h_40m_expired(self.working_block_num, prev_block_num);
-            let input_script = &mut tx_input.script;
-            if expired {
-                if input_script.is_expired_pkhxr_input() {
-                    // no need to sign expired pkhx
-                    return Ok(self.tx.clone());
-                } else {
-                    return Err(EbxError::GenericError {
-                        source: None,
-                        message: "expected expired pkhx input".to_string(),
-                    });
-                }
-            }
-
-            let key_pair = if input_script.is_recovery_pkhxr_input() {
-                let recoverable =
-                    Script::is_pkhxr_1h_40m_recoverable(self.working_block_num, prev_block_num);
-                if !recoverable {
-                    return Err(EbxError::GenericError {
-                        source: None,
-                        message: "expected recoverable pkhx input".to_string(),
-                    });
-                }
-                match self.pkh_key_map.get(&rpkh_buf) {
-                    Some(key) => key,
-                    None => {
-                        return Err(EbxError::GenericError {
-                            source: None,
-                            message: "key not found".to_string(),
-                        })
-                    }
-                }
-            } else if input_script.is_unexpired_pkhxr_input() {
-                match self.pkh_key_map.get(&pkh_buf) {
-                    Some(key) => key,
-                    None => {
-                        return Err(EbxError::GenericError {
-                            source: None,
-                            message: "key not found".to_string(),
-                        })
-                    }
-                }
-            } else {
+        let template = match self.script_templates.find(&tx_out_bn.tx_out.script) {
+            Some(template) => template,
+            None => {
                 return Err(EbxError::GenericError {
                     source: None,
-                    message: "expected unexpired pkhx input placeholder".to_string(),
-                });
-            };
-
-            let pub_key_buf = &key_pair.pub_key.buf.to_vec();
-            let output_script_buf = tx_out.script.to_buf();
-            let output_amount = tx_out.value;
-            let priv_key_buf = key_pair.priv_key.buf;
-            let sig = tx_clone.sign_no_cache(
-                n_in,
-                priv_key_buf,
-                output_script_buf.to_vec(),
-                output_amount,
-                TxSignature::SIGHASH_ALL,
-            );
-            let sig_buf = sig.to_buf();
-
-            input_script.chunks[0].buffer = Some(sig_buf.to_vec());
-            input_script.chunks[1].buffer = Some(pub_key_buf.clone());
-        } else if tx_out.script.is_pkhxr_90d_60d_output() {
-            let pkh_buf: [u8; 32] = tx_out.script.chunks[3]
-                .buffer
-                .clone()
-                .expect("pkh not found")
-                .try_into()
-                .unwrap();
-            let rpkh_buf: [u8; 32] = tx_out.script.chunks[13]
-                .buffer
-                .clone()
-                .expect("rpkh not found")
-                .try_into()
-                .unwrap();
-            let expired = Script::is_pkhxr_90d_60d_expired(self.working_block_num, prev_block_num);
-            let input_script = &mut tx_input.script;
-            if expired {
-                if input_script.is_expired_pkhxr_input() {
-                    // no need to sign expired pkhx
-                    return Ok(self.tx.clone());
-                } else {
-                    return Err(EbxError::GenericError {
-                        source: None,
-                        message: "expected expired pkhx input".to_string(),
-                    });
-                }
+                    message: "unsupported script type".to_string(),
+                })
             }
+        };
 
-            let key_pair = if input_script.is_recovery_pkhxr_input() {
-                let recoverable =
-                    Script::is_pkhxr_90d_60d_recoverable(self.working_block_num, prev_block_num);
-                if !recoverable {
-                    return Err(EbxError::GenericError {
-                        source: None,
-                        message: "expected recoverable pkhx input".to_string(),
-                    });
-                }
-                match self.pkh_key_map.get(&rpkh_buf) {
-                    Some(key) => key,
-                    None => {
-                        return Err(EbxError::GenericError {
-                            source: None,
-                            message: "key not found".to_string(),
-                        })
-                    }
-                }
-            } else if input_script.is_unexpired_pkhxr_input() {
-                match self.pkh_key_map.get(&pkh_buf) {
-                    Some(key) => key,
-                    None => {
-                        return Err(EbxError::GenericError {
-                            source: None,
-                            message: "key not found".to_string(),
-                        })
-                    }
-                }
-            } else {
-                return Err(EbxError::GenericError {
-                    source: None,
-                    message: "expected unexpired pkhx input placeholder".to_string(),
-                });
-            };
-
-            let pub_key_buf = &key_pair.pub_key.buf.to_vec();
-            let output_script_buf = tx_out.script.to_buf();
-            let output_amount = tx_out.value;
-            let priv_key_buf = key_pair.priv_key.buf;
-            let sig = tx_clone.sign_no_cache(
+        let input_script = template.sign_input(
+            &TemplateInput {
+                tx: &self.tx,
                 n_in,
-                priv_key_buf,
-                output_script_buf.to_vec(),
-                output_amount,
-                TxSignature::SIGHASH_ALL,
-            );
-            let sig_buf = sig.to_buf();
-
-            input_script.chunks[0].buffer = Some(sig_buf.to_vec());
-            input_script.chunks[1].buffer = Some(pub_key_buf.clone());
-        } else {
-            return Err(EbxError::GenericError {
-                source: None,
-                message: "unsupported script type".to_string(),
-            });
-        }
+                tx_out: &tx_out_bn.tx_out,
+                prev_block_num: tx_out_bn.block_num,
+                working_block_num: self.working_block_num,
+            },
+            self.signer,
+        )?;
+        self.tx.inputs[n_in].script = input_script;
 
         Ok(self.tx.clone())
     }
@@ -368,6 +88,8 @@ mod tests {
     use crate::tx_builder::TxBuilder;
     use crate::tx_out::TxOut;
     use crate::tx_out_bn_map::TxOutBnMap;
+    use crate::tx_signature::TxSignature;
+    use crate::tx_verifier::TxVerifier;
 
     #[test]
     fn should_sign_a_tx() {
@@ -414,8 +136,8 @@ mod tests {
 
         let mut hash_cache = HashCache::new();
         let mut script_interpreter = ScriptInterpreter::from_output_script_tx(
-            exec_script,
-            signed_tx,
+            &exec_script,
+            &signed_tx,
             0,
             stack,
             100,
@@ -476,8 +198,8 @@ mod tests {
 
         let mut hash_cache = HashCache::new();
         let mut script_interpreter_1 = ScriptInterpreter::from_output_script_tx(
-            exec_script_1,
-            signed_tx.clone(),
+            &exec_script_1,
+            &signed_tx,
             0,
             stack_1,
             100,
@@ -502,8 +224,8 @@ mod tests {
         let mut hash_cache = HashCache::new();
 
         let mut script_interpreter_2 = ScriptInterpreter::from_output_script_tx(
-            exec_script_2,
-            signed_tx.clone(),
+            &exec_script_2,
+            &signed_tx,
             1,
             stack_2,
             100,
@@ -513,4 +235,54 @@ mod tests {
         let result_2 = script_interpreter_2.eval_script();
         assert!(result_2);
     }
+
+    // stands in for a signer in another process: it holds one key and only
+    // ever receives pkhs and sighashes
+    struct RemoteSigner {
+        key: KeyPair,
+    }
+
+    impl Signer for RemoteSigner {
+        fn get_pub_key(&self, pkh: &[u8; 32]) -> Option<PubKey> {
+            let key_pkh = Pkh::from_pub_key_buffer(self.key.pub_key.buf.to_vec());
+            if &key_pkh.buf == pkh {
+                Some(self.key.pub_key.clone())
+            } else {
+                None
+            }
+        }
+
+        fn sign(&self, pkh: &[u8; 32], sighash: &[u8; 32]) -> Result<[u8; 64], EbxError> {
+            let mut pkh_key_map = PkhKeyMap::new();
+            pkh_key_map.add(self.key.clone(), pkh);
+            pkh_key_map.sign(pkh, sighash)
+        }
+    }
+
+    #[test]
+    fn should_sign_with_external_signer() {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        let key = KeyPair::from_random();
+        let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec());
+        let script = Script::from_pkhx_90d_output(&pkh.buf);
+        tx_out_bn_map.add(&[0; 32], 0, TxOut::new(100, script), 0);
+
+        let mut tx_builder = TxBuilder::new(&tx_out_bn_map, Script::from_empty(), 0);
+        tx_builder.add_output(TxOut::new(100, Script::from_empty()));
+        let tx = tx_builder.build().unwrap();
+
+        let signer = RemoteSigner { key };
+        let mut tx_signer = TxSigner::new(tx, &tx_out_bn_map, &signer, 0);
+        let signed_tx = tx_signer.sign().unwrap();
+
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, 0);
+        assert!(tx_verifier.verify());
+
+        let other_signer = RemoteSigner {
+            key: KeyPair::from_random(),
+        };
+        let tx = tx_builder.build().unwrap();
+        let mut tx_signer = TxSigner::new(tx, &tx_out_bn_map, &other_signer, 0);
+        assert!(tx_signer.sign().is_err());
+    }
 }
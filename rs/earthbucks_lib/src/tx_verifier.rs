@@ -1,28 +1,48 @@
 use crate::script_interpreter::ScriptInterpreter;
+use crate::sealed_tx::SealedTx;
+use crate::sig_cache::SigCache;
 use crate::tx::{HashCache, Tx};
 use crate::tx_out_bn_map::TxOutBnMap;
 
 pub struct TxVerifier<'a> {
-    tx: Tx,
+    tx: &'a Tx,
     tx_out_bn_map: &'a TxOutBnMap,
     hash_cache: HashCache,
     block_num: u32,
+    sig_cache: Option<&'a SigCache>,
 }
 
 impl<'a> TxVerifier<'a> {
-    pub fn new(tx: Tx, tx_out_bn_map: &'a TxOutBnMap, block_num: u32) -> Self {
+    pub fn new(tx: &'a Tx, tx_out_bn_map: &'a TxOutBnMap, block_num: u32) -> Self {
         let hash_cache = HashCache::new();
         Self {
             tx,
             tx_out_bn_map,
             hash_cache,
             block_num,
+            sig_cache: None,
         }
     }
 
+    // uses the hashes already computed by the sealed tx
+    pub fn from_sealed_tx(
+        sealed_tx: &'a SealedTx,
+        tx_out_bn_map: &'a TxOutBnMap,
+        block_num: u32,
+    ) -> Self {
+        let mut tx_verifier = Self::new(sealed_tx.tx(), tx_out_bn_map, block_num);
+        tx_verifier.hash_cache = sealed_tx.hash_cache();
+        tx_verifier
+    }
+
+    pub fn set_sig_cache(&mut self, sig_cache: &'a SigCache) {
+        self.sig_cache = Some(sig_cache);
+    }
+
     pub fn verify_input_script(&mut self, n_in: usize) -> bool {
-        let tx_input = &self.tx.inputs[n_in];
-        let tx_out_hash: &[u8; 32] = &tx_input.input_tx_id.clone();
+        let tx = self.tx;
+        let tx_input = &tx.inputs[n_in];
+        let tx_out_hash: &[u8; 32] = &tx_input.input_tx_id;
         let output_index = tx_input.input_tx_out_num;
         let tx_out = self.tx_out_bn_map.get(tx_out_hash, output_index);
         match tx_out {
@@ -39,13 +59,16 @@ impl<'a> TxVerifier<'a> {
                     .map(|chunk| chunk.get_data().unwrap())
                     .collect();
                 let mut script_interpreter = ScriptInterpreter::from_output_script_tx(
-                    output_script.clone(),
-                    self.tx.clone(),
+                    output_script,
+                    tx,
                     n_in,
                     stack,
                     tx_out_bn.tx_out.value,
                     &mut self.hash_cache,
                 );
+                if let Some(sig_cache) = self.sig_cache {
+                    script_interpreter.set_sig_cache(sig_cache);
+                }
                 script_interpreter.eval_script()
             }
         }
@@ -79,20 +102,21 @@ impl<'a> TxVerifier<'a> {
     }
 
     pub fn verify_no_double_spend(&self) -> bool {
+        // compare outpoints, not outputs: two different outpoints may hold
+        // identical outputs in the same block
         let mut spent_outputs = Vec::new();
         for input in &self.tx.inputs {
             let tx_out = self
                 .tx_out_bn_map
                 .get(&input.input_tx_id.clone(), input.input_tx_out_num);
-            match tx_out {
-                None => return false,
-                Some(tx_out) => {
-                    if spent_outputs.contains(&tx_out) {
-                        return false;
-                    }
-                    spent_outputs.push(tx_out);
-                }
+            if tx_out.is_none() {
+                return false;
             }
+            let outpoint = (input.input_tx_id, input.input_tx_out_num);
+            if spent_outputs.contains(&outpoint) {
+                return false;
+            }
+            spent_outputs.push(outpoint);
         }
         true
     }
@@ -197,7 +221,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -213,6 +237,10 @@ mod tests {
 
         let verified = tx_verifier.verify();
         assert!(verified);
+
+        let sealed_tx = SealedTx::new(signed_tx);
+        let mut tx_verifier = TxVerifier::from_sealed_tx(&sealed_tx, &tx_out_bn_map, 0);
+        assert!(tx_verifier.verify());
     }
 
     #[test]
@@ -248,7 +276,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, 0);
         let verified_input = tx_verifier.verify_input_script(0);
         assert!(verified_input);
 
@@ -298,7 +326,7 @@ mod tests {
         assert!(tx_res2.is_ok());
         let signed_tx = tx_signer.tx;
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, 0);
         let verified_input1 = tx_verifier.verify_input_script(0);
         assert!(verified_input1);
         let verified_input2 = tx_verifier.verify_input_script(1);
@@ -347,7 +375,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -406,7 +434,7 @@ mod tests {
 
         assert!(tx.inputs[0].script.is_expired_pkhx_input());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -457,7 +485,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -516,7 +544,7 @@ mod tests {
 
         assert!(tx.inputs[0].script.is_expired_pkhx_input());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -567,7 +595,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -629,7 +657,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -691,7 +719,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -742,7 +770,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, 0);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, 0);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -804,7 +832,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
@@ -866,7 +894,7 @@ mod tests {
         let signed_tx = tx_signer.tx;
         assert!(tx_res.is_ok());
 
-        let mut tx_verifier = TxVerifier::new(signed_tx, &tx_out_bn_map, working_block_num);
+        let mut tx_verifier = TxVerifier::new(&signed_tx, &tx_out_bn_map, working_block_num);
 
         let verified_input_script = tx_verifier.verify_input_script(0);
         assert!(verified_input_script);
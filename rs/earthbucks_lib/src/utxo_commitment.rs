@@ -0,0 +1,182 @@
+use crate::buf_writer::BufWriter;
+use crate::hash::double_blake3_hash;
+use crate::tx_out_bn::TxOutBn;
+use crate::tx_out_bn_map::TxOutBnMap;
+use blake3::Hasher;
+use num_bigint::BigUint;
+
+// a commitment to a set of unspent outputs that doesn't depend on the order
+// the outputs were added in. this is MuHash: each output is hashed, with its
+// outpoint and block number, to a number mod a 3072 bit prime, and the set is
+// the product of those numbers. removed outputs are multiplied into a
+// separate denominator, so adding or removing an output costs one hash and
+// one multiplication. two nodes with the same outputs have the same
+// commitment, however they got there.
+//
+// finding two different sets with the same product is as hard as the
+// discrete log problem mod the prime, so unlike a sum of hashes, the
+// commitment can't be matched by choosing the outputs.
+#[derive(Debug, Clone)]
+pub struct UtxoCommitment {
+    n_outputs: u64,
+    numerator: BigUint,
+    denominator: BigUint,
+}
+
+impl Default for UtxoCommitment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialEq for UtxoCommitment {
+    fn eq(&self, other: &Self) -> bool {
+        let prime = Self::prime();
+        self.n_outputs == other.n_outputs
+            && (&self.numerator * &other.denominator) % &prime
+                == (&other.numerator * &self.denominator) % &prime
+    }
+}
+
+impl Eq for UtxoCommitment {}
+
+impl UtxoCommitment {
+    // the size in bytes of a number mod the prime
+    pub const NUM_SIZE: usize = 384;
+
+    pub fn new() -> Self {
+        Self {
+            n_outputs: 0,
+            numerator: BigUint::from(1u8),
+            denominator: BigUint::from(1u8),
+        }
+    }
+
+    // 2^3072 - 1103717, the largest 3072 bit safe prime
+    fn prime() -> BigUint {
+        (BigUint::from(1u8) << (Self::NUM_SIZE * 8)) - BigUint::from(1103717u32)
+    }
+
+    // computes the commitment from scratch, going over every output
+    pub fn from_tx_out_bn_map(tx_out_bn_map: &TxOutBnMap) -> Self {
+        let mut commitment = Self::new();
+        for (name, tx_out_bn) in tx_out_bn_map.iter() {
+            let tx_id: [u8; 32] = TxOutBnMap::name_to_tx_id(name).try_into().unwrap();
+            let tx_out_num = TxOutBnMap::name_to_tx_out_num(name);
+            commitment.add(&tx_id, tx_out_num, tx_out_bn);
+        }
+        commitment
+    }
+
+    pub fn n_outputs(&self) -> u64 {
+        self.n_outputs
+    }
+
+    fn output_num(tx_id: &[u8; 32], tx_out_num: u32, tx_out_bn: &TxOutBn) -> BigUint {
+        let mut bw = BufWriter::new();
+        bw.write(tx_id.to_vec());
+        bw.write_u32_be(tx_out_num);
+        bw.write_u32_be(tx_out_bn.block_num);
+        bw.write(tx_out_bn.tx_out.to_buf());
+        let mut hasher = Hasher::new();
+        hasher.update(&bw.to_buf());
+        let mut buf = [0u8; Self::NUM_SIZE];
+        hasher.finalize_xof().fill(&mut buf);
+        BigUint::from_bytes_be(&buf) % Self::prime()
+    }
+
+    pub fn add(&mut self, tx_id: &[u8; 32], tx_out_num: u32, tx_out_bn: &TxOutBn) {
+        let num = Self::output_num(tx_id, tx_out_num, tx_out_bn);
+        self.numerator = (&self.numerator * num) % Self::prime();
+        self.n_outputs += 1;
+    }
+
+    // the output must be in the set
+    pub fn remove(&mut self, tx_id: &[u8; 32], tx_out_num: u32, tx_out_bn: &TxOutBn) {
+        let num = Self::output_num(tx_id, tx_out_num, tx_out_bn);
+        self.denominator = (&self.denominator * num) % Self::prime();
+        self.n_outputs -= 1;
+    }
+
+    // numerator / denominator mod the prime, which is the same for every way
+    // of getting to the set. this takes a modular inverse, so it is only done
+    // when the commitment is serialized.
+    fn value(&self) -> BigUint {
+        let prime = Self::prime();
+        let inverse = self
+            .denominator
+            .modpow(&(&prime - BigUint::from(2u8)), &prime);
+        (&self.numerator * inverse) % prime
+    }
+
+    pub fn to_buf(&self) -> Vec<u8> {
+        let value = self.value().to_bytes_be();
+        let mut bw = BufWriter::new();
+        bw.write_u64_be(self.n_outputs);
+        bw.write(vec![0u8; Self::NUM_SIZE - value.len()]);
+        bw.write(value);
+        bw.to_buf()
+    }
+
+    // the 32 bytes to compare between nodes
+    pub fn hash(&self) -> [u8; 32] {
+        double_blake3_hash(&self.to_buf())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Script;
+    use crate::tx_out::TxOut;
+
+    fn tx_out_bn(value: u64, block_num: u32) -> TxOutBn {
+        TxOutBn {
+            tx_out: TxOut::new(value, Script::from_pkh_output(&[value as u8; 32])),
+            block_num,
+        }
+    }
+
+    #[test]
+    fn test_order_independent() {
+        let outputs: Vec<([u8; 32], u32, TxOutBn)> = (0..20u8)
+            .map(|i| ([i / 3; 32], i as u32 % 3, tx_out_bn(i as u64, i as u32)))
+            .collect();
+        let mut forward = UtxoCommitment::new();
+        for (tx_id, tx_out_num, tx_out_bn) in &outputs {
+            forward.add(tx_id, *tx_out_num, tx_out_bn);
+        }
+        let mut backward = UtxoCommitment::new();
+        for (tx_id, tx_out_num, tx_out_bn) in outputs.iter().rev() {
+            backward.add(tx_id, *tx_out_num, tx_out_bn);
+        }
+        assert_eq!(forward, backward);
+        assert_eq!(forward.hash(), backward.hash());
+        assert_eq!(forward.n_outputs(), 20);
+
+        // removing every output gets back to the empty set
+        for (tx_id, tx_out_num, tx_out_bn) in &outputs {
+            forward.remove(tx_id, *tx_out_num, tx_out_bn);
+        }
+        assert_eq!(forward, UtxoCommitment::new());
+        assert_eq!(forward.hash(), UtxoCommitment::new().hash());
+        assert_eq!(forward.to_buf().len(), 8 + UtxoCommitment::NUM_SIZE);
+    }
+
+    #[test]
+    fn test_commits_to_every_field() {
+        let mut commitment = UtxoCommitment::new();
+        commitment.add(&[1; 32], 0, &tx_out_bn(5, 1));
+        let hash = commitment.hash();
+        for (tx_id, tx_out_num, tx_out_bn) in [
+            ([2; 32], 0, tx_out_bn(5, 1)),
+            ([1; 32], 1, tx_out_bn(5, 1)),
+            ([1; 32], 0, tx_out_bn(6, 1)),
+            ([1; 32], 0, tx_out_bn(5, 2)),
+        ] {
+            let mut other = UtxoCommitment::new();
+            other.add(&tx_id, tx_out_num, &tx_out_bn);
+            assert_ne!(other.hash(), hash);
+        }
+    }
+}
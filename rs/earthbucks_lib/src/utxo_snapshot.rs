@@ -0,0 +1,260 @@
+use crate::buf_reader::BufReader;
+use crate::buf_writer::BufWriter;
+use crate::error::EbxError;
+use crate::hash::blake3_hash;
+use crate::header_chain::HeaderChain;
+use crate::tx_out::TxOut;
+use crate::tx_out_bn_map::TxOutBnMap;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+// the unspent outputs as of a block. outputs expire, so the unspent outputs
+// are all the state a node needs to validate the blocks after it, and a new
+// node can start from a snapshot instead of replaying the whole chain. the
+// file is:
+//
+//     magic, version (u8), block number (u32), block id, commitment hash,
+//     output count (var int), outputs, checksum (blake3 of everything before)
+//
+// each output is its tx id, output number (u32), block number (u32) and the
+// output itself. the commitment is the hash of the UtxoCommitment of the
+// outputs. it is collision resistant, so a snapshot whose outputs match a
+// commitment we trust has the outputs we expect, whoever it came from.
+#[derive(Debug, Clone)]
+pub struct UtxoSnapshot {
+    pub block_num: u32,
+    pub block_id: [u8; 32],
+    pub tx_out_bn_map: TxOutBnMap,
+}
+
+impl UtxoSnapshot {
+    pub const MAGIC: [u8; 4] = *b"EBXU";
+    pub const VERSION: u8 = 1;
+
+    // tx_out_bn_map is the state after the block with block_num and block_id
+    pub fn new(block_num: u32, block_id: [u8; 32], tx_out_bn_map: TxOutBnMap) -> Self {
+        Self {
+            block_num,
+            block_id,
+            tx_out_bn_map,
+        }
+    }
+
+    fn error(message: &str) -> EbxError {
+        EbxError::GenericError {
+            source: None,
+            message: message.to_string(),
+        }
+    }
+
+    fn io_error(error: std::io::Error) -> EbxError {
+        Self::error(&error.to_string())
+    }
+
+    pub fn commitment(&self) -> [u8; 32] {
+        self.tx_out_bn_map.commitment().hash()
+    }
+
+    pub fn to_buf(&self) -> Vec<u8> {
+        let mut bw = BufWriter::new();
+        bw.write(Self::MAGIC.to_vec());
+        bw.write_u8(Self::VERSION);
+        bw.write_u32_be(self.block_num);
+        bw.write(self.block_id.to_vec());
+        bw.write(self.commitment().to_vec());
+        bw.write_var_int(self.tx_out_bn_map.len() as u64);
+        // sorted, so the same state always gives the same file
+        let mut outputs: Vec<_> = self.tx_out_bn_map.iter().collect();
+        outputs.sort_unstable_by_key(|(name, _)| *name);
+        for (name, tx_out_bn) in outputs {
+            bw.write(TxOutBnMap::name_to_tx_id(name));
+            bw.write_u32_be(TxOutBnMap::name_to_tx_out_num(name));
+            bw.write_u32_be(tx_out_bn.block_num);
+            bw.write(tx_out_bn.tx_out.to_buf());
+        }
+        let mut buf = bw.to_buf();
+        let checksum = blake3_hash(&buf);
+        buf.extend_from_slice(&checksum);
+        buf
+    }
+
+    // reads a snapshot and checks that its outputs match its commitment. the
+    // commitment comes from the snapshot itself, so this only catches
+    // corruption; see verify for checking it against a trusted commitment.
+    pub fn from_buf(buf: Vec<u8>) -> Result<Self, EbxError> {
+        if buf.len() < 32 {
+            return Err(EbxError::NotEnoughDataError { source: None });
+        }
+        let (body, checksum) = buf.split_at(buf.len() - 32);
+        if blake3_hash(body) != checksum {
+            return Err(Self::error("snapshot checksum is invalid"));
+        }
+        let mut br = BufReader::new(body.to_vec());
+        if br.read(4)? != Self::MAGIC {
+            return Err(Self::error("not a utxo snapshot"));
+        }
+        if br.read_u8()? != Self::VERSION {
+            return Err(Self::error("unsupported snapshot version"));
+        }
+        let block_num = br.read_u32_be()?;
+        let block_id: [u8; 32] = br.read(32)?.try_into().unwrap();
+        let commitment: [u8; 32] = br.read(32)?.try_into().unwrap();
+        let n_outputs = br.read_var_int()?;
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        for _ in 0..n_outputs {
+            let tx_id: [u8; 32] = br.read(32)?.try_into().unwrap();
+            let tx_out_num = br.read_u32_be()?;
+            let tx_out_block_num = br.read_u32_be()?;
+            let tx_out = TxOut::from_buf_reader(&mut br)?;
+            if tx_out_block_num > block_num {
+                return Err(Self::error("output is newer than snapshot"));
+            }
+            if tx_out_bn_map.get(&tx_id, tx_out_num).is_some() {
+                return Err(Self::error("output is in snapshot twice"));
+            }
+            tx_out_bn_map.add(&tx_id, tx_out_num, tx_out, tx_out_block_num);
+        }
+        if !br.eof() {
+            return Err(EbxError::TooMuchDataError { source: None });
+        }
+        let snapshot = Self::new(block_num, block_id, tx_out_bn_map);
+        if snapshot.commitment() != commitment {
+            return Err(Self::error("outputs do not match snapshot commitment"));
+        }
+        Ok(snapshot)
+    }
+
+    // writes the snapshot to path, through a temporary file that is then
+    // renamed, so a crash never leaves half a snapshot
+    pub fn save(&self, path: &Path) -> Result<(), EbxError> {
+        let tmp_path = path.with_extension("tmp");
+        let mut file = File::create(&tmp_path).map_err(Self::io_error)?;
+        file.write_all(&self.to_buf()).map_err(Self::io_error)?;
+        file.sync_all().map_err(Self::io_error)?;
+        fs::rename(&tmp_path, path).map_err(Self::io_error)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, EbxError> {
+        Self::from_buf(fs::read(path).map_err(Self::io_error)?)
+    }
+
+    // checks, before starting from the snapshot, that its block is in lch
+    // and that its outputs have the commitment we trust, e.g. one from
+    // another of our nodes or a checkpoint. since the commitment is collision
+    // resistant, this authenticates a snapshot from an untrusted source. the
+    // blocks after it are then validated against tx_out_bn_map as usual.
+    pub fn verify(&self, lch: &HeaderChain, trusted_commitment: &[u8; 32]) -> Result<(), EbxError> {
+        match lch.headers().get(self.block_num as usize) {
+            Some(header) if header.id() == self.block_id => {}
+            _ => return Err(Self::error("snapshot block is not in header chain")),
+        }
+        if self.commitment() != *trusted_commitment {
+            return Err(Self::error("snapshot commitment is not trusted"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::block_verifier::BlockVerifier;
+    use crate::key_pair::KeyPair;
+    use crate::merkle_txs::MerkleTxs;
+    use crate::pkh::Pkh;
+    use crate::pkh_key_map::PkhKeyMap;
+    use crate::script::Script;
+    use crate::tx_builder::TxBuilder;
+    use crate::tx_signer::TxSigner;
+
+    fn temp_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("earthbucks_utxos_{}.dat", rand::random::<u64>()))
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        for i in 0..10u8 {
+            let tx_out = TxOut::new(i as u64, Script::from_pkh_output(&[i; 32]));
+            tx_out_bn_map.add(&[i; 32], i as u32 % 2, tx_out, i as u32 / 2);
+        }
+        let snapshot = UtxoSnapshot::new(5, [7; 32], tx_out_bn_map);
+        let path = temp_path();
+        snapshot.save(&path).unwrap();
+        let loaded = UtxoSnapshot::load(&path).unwrap();
+        assert_eq!(loaded.block_num, 5);
+        assert_eq!(loaded.block_id, [7; 32]);
+        assert_eq!(loaded.commitment(), snapshot.commitment());
+        assert_eq!(loaded.to_buf(), snapshot.to_buf());
+        assert_eq!(
+            loaded.tx_out_bn_map.get(&[3; 32], 1),
+            snapshot.tx_out_bn_map.get(&[3; 32], 1)
+        );
+        fs::remove_file(&path).unwrap();
+
+        // a corrupt file, or outputs that don't match the commitment, fail
+        let mut buf = snapshot.to_buf();
+        buf[10] ^= 1;
+        assert!(UtxoSnapshot::from_buf(buf).is_err());
+        let mut buf = snapshot.to_buf();
+        buf.truncate(buf.len() - 32);
+        let commitment_start = 4 + 1 + 4 + 32;
+        buf[commitment_start] ^= 1;
+        let checksum = blake3_hash(&buf);
+        buf.extend_from_slice(&checksum);
+        assert!(UtxoSnapshot::from_buf(buf).is_err());
+        assert!(UtxoSnapshot::from_buf(vec![0; 10]).is_err());
+    }
+
+    #[test]
+    fn test_bootstrap_and_validate_forward() {
+        let key = KeyPair::from_random();
+        let pkh = Pkh::from_pub_key_buffer(key.pub_key.buf.to_vec());
+        let mut pkh_key_map = PkhKeyMap::new();
+        pkh_key_map.add(key, &pkh.buf);
+        let script = Script::from_pkh_output(&pkh.buf);
+        let domain = "example.com".to_string();
+
+        // the state after the genesis block, with an output we can spend
+        let mut lch = HeaderChain::new();
+        let coinbase_tx = lch.get_next_coinbase_tx(&pkh, &domain);
+        let mut tx_out_bn_map = TxOutBnMap::new();
+        tx_out_bn_map.add_tx_outputs(&coinbase_tx, 0);
+        tx_out_bn_map.add(&[1; 32], 0, TxOut::new(1000, script.clone()), 0);
+        let merkle_root = MerkleTxs::new(vec![coinbase_tx]).root;
+        lch.add(lch.get_next_header(merkle_root, 0).unwrap());
+        let snapshot = UtxoSnapshot::new(0, lch.headers()[0].id(), tx_out_bn_map);
+        let commitment = snapshot.commitment();
+
+        // a new node imports the snapshot and validates the next block
+        let path = temp_path();
+        snapshot.save(&path).unwrap();
+        let imported = UtxoSnapshot::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        imported.verify(&lch, &commitment).unwrap();
+        assert!(imported.verify(&lch, &[0; 32]).is_err());
+        assert!(imported.verify(&HeaderChain::new(), &commitment).is_err());
+
+        // a snapshot with other outputs doesn't have the trusted commitment
+        let mut tampered = imported.clone();
+        tampered
+            .tx_out_bn_map
+            .add(&[3; 32], 0, TxOut::new(1000, script.clone()), 0);
+        assert!(tampered.verify(&lch, &commitment).is_err());
+
+        let mut unspent = TxOutBnMap::new();
+        unspent.add(&[1; 32], 0, TxOut::new(1000, script.clone()), 0);
+        let mut tx_builder = TxBuilder::new(&unspent, script, 1);
+        tx_builder.add_output(TxOut::new(1, Script::from_pkh_output(&[2; 32])));
+        let tx = tx_builder.build().unwrap();
+        let tx = TxSigner::new(tx, &unspent, &pkh_key_map, 1).sign().unwrap();
+        let txs = vec![lch.get_next_coinbase_tx(&pkh, &domain), tx];
+        let merkle_root = MerkleTxs::new(txs.clone()).root;
+        let header = lch.get_next_header(merkle_root, 600_000).unwrap();
+        let block = Block::new(header, txs);
+        let mut block_verifier = BlockVerifier::new(block, imported.tx_out_bn_map, &lch);
+        assert_eq!(block_verifier.verify_at(600_000), Ok(()));
+    }
+}